@@ -1,12 +1,23 @@
 use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
 use std::{hint::black_box, time::Duration};
+use vanity_ssh_rs::core::backend::KeyBackend;
+use vanity_ssh_rs::core::ecdsa::{Curve, generate_ecdsa_batch};
 use vanity_ssh_rs::core::keypair::bench_helpers::*;
-use vanity_ssh_rs::core::keypair::{BATCH_SIZE, generate_keypair_batch};
+use vanity_ssh_rs::core::keypair::{
+    BATCH_SIZE, Ed25519Backend, Strategy, generate_keypair_batch, generate_keypair_batch_point_walk,
+};
+use vanity_ssh_rs::core::layout::BASE64_ALPHABET;
 use vanity_ssh_rs::core::pattern::{Pattern, public_key_matches_pattern};
+use vanity_ssh_rs::core::pattern_set::PatternSet;
+use vanity_ssh_rs::core::rsa::{DEFAULT_BITS, RsaBackend};
 use vanity_ssh_rs::worker::generator::generate_and_check_batch;
 
 const MEASUREMENT_SECS: u64 = 10;
 
+const ED25519: Ed25519Backend = Ed25519Backend {
+    strategy: Strategy::Seed,
+};
+
 fn measurement_time() -> Duration {
     Duration::from_secs(MEASUREMENT_SECS)
 }
@@ -28,6 +39,56 @@ fn bench_generate_key_batch(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_generate_key_batch_point_walk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_keypair_batch_point_walk");
+    group.measurement_time(measurement_time());
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    group.bench_with_input(
+        BenchmarkId::from_parameter(BATCH_SIZE),
+        &BATCH_SIZE,
+        |b, &size| {
+            b.iter(|| {
+                let key_pairs = generate_keypair_batch_point_walk(size);
+                black_box(key_pairs)
+            })
+        },
+    );
+    group.finish();
+}
+
+fn bench_generate_ecdsa_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_ecdsa_batch");
+    group.measurement_time(measurement_time());
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    for curve in [Curve::NistP256, Curve::NistP384, Curve::NistP521] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(curve.name()),
+            &curve,
+            |b, &curve| {
+                b.iter(|| {
+                    let key_pairs = generate_ecdsa_batch(curve, BATCH_SIZE);
+                    black_box(key_pairs)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_generate_rsa_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_rsa_batch");
+    group.measurement_time(measurement_time());
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    let backend = RsaBackend::generate(DEFAULT_BITS as usize).unwrap();
+    group.bench_function("exponents", |b| {
+        b.iter(|| {
+            let key_pairs = backend.generate_batch(BATCH_SIZE);
+            black_box(key_pairs)
+        })
+    });
+    group.finish();
+}
+
 fn bench_check_suffix_batch(c: &mut Criterion) {
     let mut group = c.benchmark_group("public_key_matches_pattern_batch");
     group.measurement_time(measurement_time());
@@ -36,7 +97,7 @@ fn bench_check_suffix_batch(c: &mut Criterion) {
         BenchmarkId::from_parameter(BATCH_SIZE),
         &BATCH_SIZE,
         |b, &size| {
-            let pattern = Pattern::Suffix("yee".to_string());
+            let pattern = Pattern::new("yee".to_string()).unwrap();
             b.iter(|| {
                 let key_pairs = generate_keypair_batch(size);
                 let hits = key_pairs
@@ -50,20 +111,84 @@ fn bench_check_suffix_batch(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_match_only(c: &mut Criterion) {
+    let mut group = c.benchmark_group("public_key_matches_pattern_match_only");
+    group.measurement_time(measurement_time());
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    let key_pairs = generate_keypair_batch(BATCH_SIZE);
+    // The regex is equivalent to the suffix but goes through the string path,
+    // which shows the cost saved by the byte-level mask.
+    for pattern in ["yee", "/yee$/"] {
+        let compiled = Pattern::new(pattern.to_string()).unwrap();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(pattern),
+            &compiled,
+            |b, compiled| {
+                b.iter(|| {
+                    let hits = key_pairs
+                        .iter()
+                        .filter(|kp| public_key_matches_pattern(kp, compiled))
+                        .count();
+                    black_box(hits)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
 fn bench_generate_and_check_batch(c: &mut Criterion) {
     let mut group = c.benchmark_group("generate_and_check_batch");
     group.measurement_time(measurement_time());
     group.throughput(Throughput::Elements(BATCH_SIZE as u64));
-    let patterns = vec![Pattern::Suffix("yee".to_string())];
+    let patterns = PatternSet::new(vec![Pattern::new("yee".to_string()).unwrap()]);
     group.bench_function("generate_and_check_batch", |b| {
         b.iter(|| {
-            let result = generate_and_check_batch(&patterns);
-            black_box(result)
+            let result = generate_and_check_batch(&ED25519, &patterns, None, false);
+            black_box(result.search_hit.is_some())
         })
     });
     group.finish();
 }
 
+fn bench_find_match_many_suffixes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find_match_suffixes");
+    group.measurement_time(measurement_time());
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    let key_pairs = ED25519.generate_batch(BATCH_SIZE);
+    // Distinct four-character suffixes, so none of them shadows another.
+    let suffixes: Vec<String> = BASE64_ALPHABET
+        .iter()
+        .flat_map(|&a| {
+            BASE64_ALPHABET
+                .iter()
+                .map(move |&b| format!("yo{}{}", a as char, b as char))
+        })
+        .collect();
+    for n_patterns in [1, 10, 100, 1000] {
+        let patterns = PatternSet::new(
+            suffixes[..n_patterns]
+                .iter()
+                .map(|s| Pattern::new(s.clone()).unwrap())
+                .collect(),
+        );
+        group.bench_with_input(
+            BenchmarkId::from_parameter(n_patterns),
+            &patterns,
+            |b, patterns| {
+                b.iter(|| {
+                    let hits = key_pairs
+                        .iter()
+                        .filter(|kp| ED25519.find_match(kp, patterns).is_some())
+                        .count();
+                    black_box(hits)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
 fn bench_secret_key_generation(c: &mut Criterion) {
     let mut group = c.benchmark_group("key_generation_components");
     group.measurement_time(measurement_time());
@@ -121,8 +246,13 @@ fn bench_compress_point(c: &mut Criterion) {
 criterion_group!(
     benches,
     bench_generate_key_batch,
+    bench_generate_key_batch_point_walk,
+    bench_generate_ecdsa_batch,
+    bench_generate_rsa_batch,
     bench_check_suffix_batch,
+    bench_match_only,
     bench_generate_and_check_batch,
+    bench_find_match_many_suffixes,
     bench_secret_key_generation,
     bench_expand_secret_key,
     bench_mul_base,
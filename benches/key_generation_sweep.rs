@@ -31,7 +31,7 @@ fn bench_check_suffix_batch_sweep(c: &mut Criterion) {
     for &size in SWEEP_SIZES {
         group.throughput(Throughput::Elements(size as u64));
         group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
-            let pattern = Pattern::Suffix("yee".to_string());
+            let pattern = Pattern::new("yee".to_string()).unwrap();
             b.iter(|| {
                 let key_pairs = generate_keypair_batch(size);
                 let hits = key_pairs
@@ -1,12 +1,128 @@
 use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::core::dictionary::WordPosition;
+use crate::core::file_io::DEFAULT_NAME_TEMPLATE;
+use crate::core::keypair::{KeyType, Strategy};
+use crate::core::rsa::DEFAULT_BITS;
+use crate::core::score::{ScoreWeights, parse_score_weights};
+use crate::core::target::Target;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// The patterns to match in the public key. Use /regex/ for regex patterns, otherwise matches suffix.
-    /// Multiple patterns can be specified, any match will be accepted.
+    /// The patterns to match in the public key, any of which is accepted.
+    /// Plain text or `suffix:<text>` matches the end of the key, `prefix:`
+    /// the start after the fixed header, `contains:` anywhere, `at:<offset>:`
+    /// a fixed position, `ci:` and `lookalike:` a case-insensitive suffix,
+    /// `hex:` the key bytes, `/regex/` or `re:` a regex, and `fp:<pattern>`
+    /// the SHA256 fingerprint. Combine them with `&`, `|`, `!` (or `AND`,
+    /// `OR`, `NOT`) and parentheses, e.g. "prefix:dev & (ops | ci:sec)"
     pub patterns: Vec<String>,
 
+    /// Read more patterns from a file, one per line. Empty lines and lines
+    /// starting with `#` are ignored
+    #[arg(long)]
+    pub patterns_file: Option<PathBuf>,
+
+    /// Accept any word from this file, one per line, as a pattern
+    #[arg(long)]
+    pub dictionary: Option<PathBuf>,
+
+    /// Shortest dictionary word to accept
+    #[arg(long, default_value_t = 4)]
+    pub min_len: usize,
+
+    /// Where dictionary words are looked for
+    #[arg(long, value_enum, default_value_t = WordPosition::Tail)]
+    pub dictionary_position: WordPosition,
+
+    /// Match dictionary words case-insensitively
+    #[arg(long)]
+    pub dictionary_ignore_case: bool,
+
+    /// Instead of (or next to) matching patterns, score every key and keep
+    /// the best ones, e.g. `word=2,repeat,palindrome`. `word` is the longest
+    /// word from --dictionary, `repeat` the longest run of one character and
+    /// `palindrome` the longest palindrome
+    #[arg(long, value_parser = parse_score_weights)]
+    pub score: Option<ScoreWeights>,
+
+    /// Number of best keys kept on disk in scoring mode
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    pub top: u64,
+
+    /// Directory the keys are saved in
+    #[arg(long, default_value = "out")]
+    pub out_dir: PathBuf,
+
+    /// Name of saved keys, with the placeholders {pattern}, {fingerprint},
+    /// {timestamp} and {n} (keys saved so far). Names that are taken get
+    /// `_2`, `_3`, ... appended
+    #[arg(long, default_value = DEFAULT_NAME_TEMPLATE)]
+    pub name_template: String,
+
+    /// Comment of saved OpenSSH keys (defaults to user@host)
+    #[arg(long)]
+    pub comment: Option<String>,
+
+    /// Encrypt private keys with the passphrase on the first line of this file
+    #[arg(long, group = "passphrase")]
+    pub passphrase_file: Option<PathBuf>,
+
+    /// Encrypt private keys with the passphrase in this environment variable
+    #[arg(long, group = "passphrase", value_name = "VAR")]
+    pub passphrase_env: Option<String>,
+
+    /// Ask for a passphrase to encrypt private keys with
+    #[arg(long, group = "passphrase")]
+    pub ask_passphrase: bool,
+
+    /// bcrypt-pbkdf rounds for encrypted private keys, like `ssh-keygen -a`
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(1..))]
+    pub kdf_rounds: u32,
+
+    /// Install the first key found as PATH and PATH.pub, backing up a key
+    /// already there. Stops after that key. PATH defaults to ~/.ssh/id_ed25519,
+    /// ~/.ssh/id_ecdsa or ~/.ssh/id_rsa
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        default_missing_value = "",
+        value_parser = install_path,
+        conflicts_with_all = ["score", "max_keys"]
+    )]
+    pub install: Option<PathBuf>,
+
+    /// Add a `Host` block for this alias using the installed key to
+    /// ~/.ssh/config
+    #[arg(long, value_name = "ALIAS", requires = "install")]
+    pub ssh_host: Option<String>,
+
+    /// Keep the secrets of found keys in memory locked with mlock, so they
+    /// are never written to swap
+    #[arg(long)]
+    pub lock_memory: bool,
+
+    /// What to generate a key for
+    #[arg(long, value_enum, default_value_t = Target::default())]
+    pub target: Target,
+
+    /// Type of the OpenSSH key
+    #[arg(long, value_enum, default_value_t = KeyType::Ed25519)]
+    pub key_type: KeyType,
+
+    /// Size of the modulus with --key-type rsa
+    #[arg(long, default_value_t = DEFAULT_BITS, value_parser = clap::value_parser!(u64).range(2048..=4096))]
+    pub rsa_bits: u64,
+
+    /// How candidate keys are generated. `point-walk` is much faster but only
+    /// works with targets that store the expanded secret key (onion, wireguard)
+    #[arg(long, value_enum, default_value_t = Strategy::Seed)]
+    pub strategy: Strategy,
+
     /// Number of threads to use (defaults to number of CPU cores)
     #[arg(short, long)]
     pub threads: Option<usize>,
@@ -18,4 +134,47 @@ pub struct Args {
     #[arg(long, short, default_value = "false")]
     /// Stop after first match
     pub stop_after_match: bool,
+
+    /// Number of keys to collect per pattern before it is dropped from the search.
+    /// Give one value for all patterns or one value per pattern, in order.
+    #[arg(long, short, value_delimiter = ',')]
+    pub count: Vec<usize>,
+
+    /// Stop after this many keys have been found in total
+    #[arg(long)]
+    pub max_keys: Option<usize>,
+
+    /// Stop after this much time has passed (e.g. "30m", "2h 30m")
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub timeout: Option<Duration>,
+
+    /// Stop after this many keys have been generated
+    #[arg(long)]
+    pub max_attempts: Option<u64>,
+}
+
+/// Parses the path of `--install`, which is empty when the option is given
+/// without one. Clap's own path parser rejects empty values.
+fn install_path(path: &str) -> Result<PathBuf, std::convert::Infallible> {
+    Ok(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install(options: &[&str]) -> Option<PathBuf> {
+        Args::parse_from(["vanity-ssh-rs", "abc"].iter().chain(options)).install
+    }
+
+    #[test]
+    fn install_path_is_optional() {
+        assert_eq!(install(&[]), None);
+        assert_eq!(install(&["--install"]), Some(PathBuf::new()));
+        assert_eq!(install(&["--install", "-t", "2"]), Some(PathBuf::new()));
+        assert_eq!(
+            install(&["--install", "~/.ssh/id_dev"]),
+            Some(PathBuf::from("~/.ssh/id_dev"))
+        );
+    }
 }
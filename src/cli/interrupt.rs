@@ -0,0 +1,49 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Exit code conventionally used for processes terminated by SIGINT.
+const FORCED_EXIT_CODE: i32 = 130;
+
+/// Sets `stop_flag` on the first SIGINT/SIGTERM so the search can wind down
+/// and print its summary. A second signal exits immediately.
+pub fn install_interrupt_handler(stop_flag: Arc<AtomicBool>) -> Result<(), ctrlc::Error> {
+    // Counted apart from `stop_flag`, which the manager also sets when it
+    // stops on its own.
+    let signals = AtomicUsize::new(0);
+    ctrlc::set_handler(move || {
+        if handle_signal(&signals, &stop_flag) {
+            std::process::exit(FORCED_EXIT_CODE);
+        }
+    })
+}
+
+/// Asks the search to stop and returns whether this was not the first
+/// signal, so the process should exit right away.
+fn handle_signal(signals: &AtomicUsize, stop_flag: &AtomicBool) -> bool {
+    stop_flag.store(true, Ordering::Relaxed);
+    signals.fetch_add(1, Ordering::Relaxed) > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_signal_stops_and_second_exits() {
+        let signals = AtomicUsize::new(0);
+        let stop_flag = AtomicBool::new(false);
+        assert!(!handle_signal(&signals, &stop_flag));
+        assert!(stop_flag.load(Ordering::Relaxed));
+        assert!(handle_signal(&signals, &stop_flag));
+    }
+
+    /// The manager sets the stop flag when it stops on its own and then
+    /// waits for the workers, which a first Ctrl-C must not cut short.
+    #[test]
+    fn stop_flag_set_by_the_manager_is_not_a_signal() {
+        let signals = AtomicUsize::new(0);
+        let stop_flag = AtomicBool::new(true);
+        assert!(!handle_signal(&signals, &stop_flag));
+        assert!(handle_signal(&signals, &stop_flag));
+    }
+}
@@ -1,3 +1,6 @@
 pub mod args;
+pub mod interrupt;
+pub mod passphrase;
+pub mod patterns_file;
 
 pub use args::Args;
@@ -0,0 +1,145 @@
+use std::env;
+use std::fs;
+use std::io;
+
+use zeroize::Zeroizing;
+
+use super::Args;
+
+/// Whether one of the passphrase options was given, checked before
+/// [`read_passphrase`] prompts for it or reads it.
+pub fn passphrase_requested(args: &Args) -> bool {
+    args.passphrase_file.is_some() || args.passphrase_env.is_some() || args.ask_passphrase
+}
+
+/// Reads the passphrase to encrypt private keys with from `--passphrase-file`,
+/// `--passphrase-env` or an interactive prompt, whichever was given.
+pub fn read_passphrase(args: &Args) -> io::Result<Option<Zeroizing<String>>> {
+    let passphrase = if let Some(path) = &args.passphrase_file {
+        // Only the first line counts, as with `ssh-keygen -P "$(cat file)"`.
+        let contents = Zeroizing::new(fs::read_to_string(path)?);
+        Zeroizing::new(contents.lines().next().unwrap_or("").to_string())
+    } else if let Some(name) = &args.passphrase_env {
+        Zeroizing::new(env::var(name).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("cannot read ${}: {}", name, e),
+            )
+        })?)
+    } else if args.ask_passphrase {
+        let passphrase = Zeroizing::new(rpassword::prompt_password(
+            "Passphrase for the private keys: ",
+        )?);
+        let again = Zeroizing::new(rpassword::prompt_password("Same passphrase again: ")?);
+        if passphrase != again {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the passphrases do not match",
+            ));
+        }
+        passphrase
+    } else {
+        return Ok(None);
+    };
+
+    // An empty passphrase would silently write unencrypted keys.
+    if passphrase.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the passphrase is empty",
+        ));
+    }
+    Ok(Some(passphrase))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use clap::Parser;
+
+    use super::*;
+
+    /// Cargo sets this for the tests it runs, so no test has to set a
+    /// variable while others may read the environment.
+    const ENV_VAR: &str = "CARGO_PKG_NAME";
+
+    fn passphrase_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "vanity-ssh-rs-passphrase-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn args(file: Option<&PathBuf>, env: Option<&str>, ask: bool) -> Args {
+        let mut args = Args::parse_from(["vanity-ssh-rs", "abc"]);
+        args.passphrase_file = file.cloned();
+        args.passphrase_env = env.map(str::to_string);
+        args.ask_passphrase = ask;
+        args
+    }
+
+    fn read(args: &Args) -> io::Result<Option<String>> {
+        read_passphrase(args).map(|passphrase| passphrase.map(|p| p.to_string()))
+    }
+
+    #[test]
+    fn options_are_exclusive() {
+        assert!(Args::try_parse_from(["vanity-ssh-rs", "abc", "--ask-passphrase"]).is_ok());
+        assert!(
+            Args::try_parse_from([
+                "vanity-ssh-rs",
+                "abc",
+                "--passphrase-env",
+                "X",
+                "--ask-passphrase"
+            ])
+            .is_err()
+        );
+    }
+
+    /// The file comes before the variable and both before the prompt, which
+    /// is therefore never shown here.
+    #[test]
+    fn file_then_env_then_prompt() {
+        let file = passphrase_file("order", "from file\nsecond line\n");
+        assert!(!passphrase_requested(&args(None, None, false)));
+        assert_eq!(read(&args(None, None, false)).unwrap(), None);
+
+        let file_args = args(Some(&file), Some(ENV_VAR), true);
+        assert!(passphrase_requested(&file_args));
+        assert_eq!(read(&file_args).unwrap().unwrap(), "from file");
+
+        let env_args = args(None, Some(ENV_VAR), true);
+        assert!(passphrase_requested(&env_args));
+        assert_eq!(read(&env_args).unwrap().unwrap(), env!("CARGO_PKG_NAME"));
+
+        assert!(passphrase_requested(&args(None, None, true)));
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn missing_sources_are_errors() {
+        let missing = std::env::temp_dir().join("vanity-ssh-rs-no-such-passphrase");
+        assert!(read(&args(Some(&missing), None, false)).is_err());
+
+        let name = "VANITY_SSH_RS_NO_SUCH_PASSPHRASE";
+        let error = read(&args(None, Some(name), false)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().contains(name), "{}", error);
+    }
+
+    #[test]
+    fn empty_passphrases_are_rejected() {
+        for (name, contents) in [("empty", ""), ("newline", "\n"), ("blank", "\nsecret\n")] {
+            let file = passphrase_file(name, contents);
+            let error = read(&args(Some(&file), None, false)).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(error.to_string(), "the passphrase is empty");
+            fs::remove_file(file).unwrap();
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Reads one pattern per line, skipping empty lines and `#` comments.
+/// Surrounding whitespace is trimmed, since no pattern kind can use it.
+pub fn read_patterns_file(path: &Path) -> io::Result<Vec<String>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
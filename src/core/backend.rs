@@ -0,0 +1,95 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::file_io::KeyFileOptions;
+use crate::core::layout::Layout;
+use crate::core::pattern::Pattern;
+use crate::core::pattern_set::PatternSet;
+
+/// A key pair in a generated batch. Keys leave a batch through
+/// [`BatchKeyPair::take`], so dropping the batch wipes every secret that is
+/// still in it.
+pub trait BatchKeyPair: Send + 'static {
+    /// Moves the key pair out of the batch without leaving its secret
+    /// behind, see [`crate::core::secret::SecretBytes::take`].
+    fn take(&mut self) -> Self;
+
+    /// A deliberate second copy, for the rare key that has to be sent to the
+    /// manager twice. There is no `Clone` so that copies stay deliberate.
+    fn duplicate(&self) -> Self;
+
+    /// Moves the secret into locked memory, see
+    /// [`crate::core::secret::SecretBytes::lock`].
+    fn lock(&mut self) -> io::Result<()>;
+}
+
+/// One kind of key the search can look for: how candidates are generated,
+/// what patterns see of them, and how found keys are written. Workers and
+/// the manager are generic over it, so a new kind of key is a module with
+/// an implementation of this trait.
+pub trait KeyBackend: Clone + Send + Sync + 'static {
+    type KeyPair: BatchKeyPair;
+
+    /// Distribution of the text patterns are matched against.
+    fn layout(&self) -> &'static Layout;
+
+    fn generate_batch(&self, batch_size: usize) -> Vec<Self::KeyPair>;
+
+    /// The text patterns are matched against, e.g. the base64 key blob.
+    fn text(&self, keypair: &Self::KeyPair) -> String;
+
+    /// Puts the part of the text that is not fixed by the format into
+    /// `text`, which is reused from key to key when scoring.
+    fn variable_text(&self, keypair: &Self::KeyPair, text: &mut Vec<u8>) {
+        text.clear();
+        text.extend_from_slice(&self.text(keypair).as_bytes()[self.layout().variable_start()..]);
+    }
+
+    /// Returns the first pattern the key pair matches.
+    fn find_match<'a>(
+        &self,
+        keypair: &Self::KeyPair,
+        patterns: &'a PatternSet,
+    ) -> Option<&'a Pattern>;
+
+    /// The word a dictionary pattern found in the key pair, if any.
+    fn matched_word(&self, keypair: &Self::KeyPair, pattern: &Pattern) -> Option<String> {
+        let Pattern::Dictionary(dictionary) = pattern else {
+            return None;
+        };
+        let text = self.text(keypair);
+        dictionary
+            .find_word(|i| text.as_bytes().get(i).copied(), self.layout())
+            .map(str::to_string)
+    }
+
+    /// How many different keys the search can generate, for backends that
+    /// can run out of them. Patterns rarer than that are rejected up front.
+    fn key_space(&self) -> Option<f64> {
+        None
+    }
+
+    /// What `{fingerprint}` in file names stands for: the SHA256
+    /// fingerprint of an SSH key, the address of an onion service, the public
+    /// key of a WireGuard key pair.
+    fn fingerprint(&self, keypair: &Self::KeyPair) -> String;
+
+    /// Writes the key pair into `dir` under the first free name of
+    /// [`crate::core::file_io::unique_names`] for `name`, and returns the
+    /// path of the private key or of the directory holding the key.
+    fn write(
+        &self,
+        keypair: &Self::KeyPair,
+        dir: &Path,
+        name: &str,
+        options: &KeyFileOptions,
+    ) -> io::Result<PathBuf>;
+
+    /// Removes what [`KeyBackend::write`] wrote to `path`.
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    /// What the manager prints once the key pair was written to `path`.
+    fn saved_message(&self, _keypair: &Self::KeyPair, path: &Path) -> String {
+        format!("Key saved to '{}'", path.display())
+    }
+}
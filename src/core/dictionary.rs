@@ -0,0 +1,307 @@
+use clap::ValueEnum;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::layout::{CharSet, Layout};
+use crate::core::pattern::Folding;
+
+/// Where in the text dictionary words are looked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, ValueEnum)]
+pub enum WordPosition {
+    /// At the end of the text
+    #[default]
+    Tail,
+    /// Right after the part the format fixes
+    Head,
+}
+
+/// Words read from a file, any of which is accepted at one end of the text.
+/// The words are kept in a trie spelled from that end, so a key is checked
+/// by walking its characters once however many words there are.
+#[derive(Debug)]
+pub struct Dictionary {
+    /// File name the words came from, for display.
+    pub name: String,
+    pub min_len: usize,
+    pub position: WordPosition,
+    pub ignore_case: bool,
+    words: Vec<String>,
+    /// Sorted `(character, child)` pairs per node. Dictionaries are large
+    /// and sparse, so this is much smaller than a full table per node.
+    children: Vec<Vec<(u8, u32)>>,
+    /// Word ending at each node.
+    terminal: Vec<Option<u32>>,
+}
+
+impl Dictionary {
+    /// Reads one word per line, skipping empty lines, `#` comments and words
+    /// shorter than `min_len` or with characters that never occur in
+    /// `layout`.
+    pub fn load(
+        path: &Path,
+        min_len: usize,
+        position: WordPosition,
+        ignore_case: bool,
+        layout: &Layout,
+    ) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+
+        let mut dictionary = Self {
+            name,
+            min_len,
+            position,
+            ignore_case,
+            words: Vec::new(),
+            children: vec![Vec::new()],
+            terminal: vec![None],
+        };
+
+        let alphabet = layout.alphabet();
+        for word in contents.lines().map(str::trim) {
+            if word.is_empty() || word.starts_with('#') || word.chars().count() < min_len {
+                continue;
+            }
+            if word
+                .chars()
+                .all(|c| !dictionary.class(c).intersection(&alphabet).is_empty())
+            {
+                dictionary.insert(word);
+            }
+        }
+
+        Ok(dictionary)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    fn class(&self, c: char) -> CharSet {
+        if self.ignore_case {
+            Folding::CaseInsensitive.class(c)
+        } else {
+            let mut class = CharSet::default();
+            if c.is_ascii() {
+                class.insert(c as u8);
+            }
+            class
+        }
+    }
+
+    fn normalize(&self, c: u8) -> u8 {
+        if self.ignore_case {
+            c.to_ascii_lowercase()
+        } else {
+            c
+        }
+    }
+
+    fn insert(&mut self, word: &str) {
+        let spelled: Vec<u8> = match self.position {
+            WordPosition::Tail => word.bytes().rev().collect(),
+            WordPosition::Head => word.bytes().collect(),
+        };
+
+        let mut node = 0;
+        for c in spelled {
+            let c = self.normalize(c);
+            node = match self.children[node].binary_search_by_key(&c, |&(c, _)| c) {
+                Ok(i) => self.children[node][i].1 as usize,
+                Err(i) => {
+                    let child = self.children.len();
+                    self.children.push(Vec::new());
+                    self.terminal.push(None);
+                    self.children[node].insert(i, (c, child as u32));
+                    child
+                }
+            };
+        }
+
+        // Words that only differ in case are the same word when ignoring it.
+        if self.terminal[node].is_none() {
+            self.terminal[node] = Some(self.words.len() as u32);
+            self.words.push(word.to_string());
+        }
+    }
+
+    /// Index of the `depth`-th character read, or `None` past the text.
+    fn index(&self, depth: usize, layout: &Layout) -> Option<usize> {
+        let index = match self.position {
+            WordPosition::Tail => layout.len().checked_sub(depth + 1)?,
+            WordPosition::Head => layout.variable_start() + depth,
+        };
+        (index < layout.len()).then_some(index)
+    }
+
+    /// Returns the longest word found at the dictionary's end of the text.
+    /// `char_at(i)` is the character at index `i` of text distributed as
+    /// `layout`.
+    pub fn find_word(
+        &self,
+        char_at: impl Fn(usize) -> Option<u8>,
+        layout: &Layout,
+    ) -> Option<&str> {
+        self.walk((0..).map_while(|depth| self.index(depth, layout).and_then(&char_at)))
+    }
+
+    /// Returns the longest word anywhere in `text`.
+    pub fn longest_word_in(&self, text: &[u8]) -> Option<&str> {
+        let words = (0..text.len()).filter_map(|i| match self.position {
+            WordPosition::Head => self.walk(text[i..].iter().copied()),
+            WordPosition::Tail => self.walk(text[..=i].iter().rev().copied()),
+        });
+        words.max_by_key(|word| word.len())
+    }
+
+    /// Follows `chars` down the trie, in the order words are spelled in it,
+    /// and returns the longest word passed on the way.
+    fn walk(&self, chars: impl Iterator<Item = u8>) -> Option<&str> {
+        let mut found = None;
+        let mut node = 0;
+        for c in chars {
+            let c = self.normalize(c);
+            node = match self.children[node].binary_search_by_key(&c, |&(c, _)| c) {
+                Ok(i) => self.children[node][i].1 as usize,
+                Err(_) => break,
+            };
+            if let Some(word) = self.terminal[node] {
+                found = Some(self.words[word as usize].as_str());
+            }
+        }
+        found
+    }
+
+    /// Probability that text distributed as `layout` has any of the words at
+    /// the dictionary's end. Every trie node is one string at fixed
+    /// positions, and the search below a word stops at the word, so each
+    /// matching text is counted exactly once.
+    pub fn probability(&self, layout: &Layout) -> f64 {
+        let mut total = 0.0;
+        let mut stack = vec![(0usize, 0usize, 1.0f64)];
+
+        while let Some((node, depth, probability)) = stack.pop() {
+            if self.terminal[node].is_some() {
+                total += probability;
+                continue;
+            }
+            let Some(index) = self.index(depth, layout) else {
+                continue;
+            };
+            let set = layout.position(index);
+            for &(c, child) in &self.children[node] {
+                let hits = self.class(c as char).intersection(&set).len();
+                if hits > 0 {
+                    let p = hits as f64 / set.len() as f64;
+                    stack.push((child as usize, depth + 1, probability * p));
+                }
+            }
+        }
+
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::core::layout::OPENSSH_LAYOUT;
+    use crate::core::onion::ONION_LAYOUT;
+
+    /// Loads `words` from a file for `layout`.
+    fn dictionary(
+        words: &str,
+        min_len: usize,
+        position: WordPosition,
+        ignore_case: bool,
+        layout: &Layout,
+    ) -> Dictionary {
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "vanity-ssh-rs-words-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        fs::write(&path, words).unwrap();
+        let dictionary = Dictionary::load(&path, min_len, position, ignore_case, layout).unwrap();
+        fs::remove_file(path).unwrap();
+        dictionary
+    }
+
+    /// The word found in `text`, which is laid out as an OpenSSH key.
+    fn find<'a>(dictionary: &'a Dictionary, text: &str) -> Option<&'a str> {
+        dictionary.find_word(|i| text.as_bytes().get(i).copied(), &OPENSSH_LAYOUT)
+    }
+
+    /// An OpenSSH key text with `head` right after the fixed header and
+    /// `tail` at the end.
+    fn key_text(head: &str, tail: &str) -> String {
+        let fixed = OPENSSH_LAYOUT.fixed_prefix();
+        let fill = OPENSSH_LAYOUT.len() - fixed.len() - head.len() - tail.len();
+        format!("{}{}{}{}", fixed, head, "A".repeat(fill), tail)
+    }
+
+    #[test]
+    fn short_and_impossible_words_are_skipped() {
+        let words = "# animals\n\ncat\ndoge\n  horse  \nno-dash\nélan\n";
+        let loaded = dictionary(words, 4, WordPosition::Tail, false, &OPENSSH_LAYOUT);
+        assert_eq!(loaded.words, ["doge", "horse"]);
+        let loaded = dictionary(words, 3, WordPosition::Tail, false, &OPENSSH_LAYOUT);
+        assert_eq!(loaded.words, ["cat", "doge", "horse"]);
+        // Onion addresses are lowercase, so capitals only fit ignoring case.
+        let loaded = dictionary("Doge\nmoon\n", 4, WordPosition::Tail, false, &ONION_LAYOUT);
+        assert_eq!(loaded.words, ["moon"]);
+        let loaded = dictionary("Doge\nmoon\n", 4, WordPosition::Tail, true, &ONION_LAYOUT);
+        assert_eq!(loaded.words, ["Doge", "moon"]);
+    }
+
+    #[test]
+    fn words_are_found_at_their_end_only() {
+        let words = "doge\norse\nhorse\n";
+        let tail = dictionary(words, 4, WordPosition::Tail, false, &OPENSSH_LAYOUT);
+        assert_eq!(find(&tail, &key_text("", "xhorse")), Some("horse"));
+        assert_eq!(find(&tail, &key_text("", "xxorse")), Some("orse"));
+        assert_eq!(find(&tail, &key_text("doge", "")), None);
+        assert_eq!(find(&tail, &key_text("", "dogex")), None);
+
+        let head = dictionary(words, 4, WordPosition::Head, false, &OPENSSH_LAYOUT);
+        assert_eq!(find(&head, &key_text("dogex", "")), Some("doge"));
+        assert_eq!(find(&head, &key_text("", "horse")), None);
+        assert_eq!(find(&head, &key_text("xdoge", "")), None);
+    }
+
+    #[test]
+    fn ignore_case_folds_words_and_text() {
+        let exact = dictionary("Doge\n", 4, WordPosition::Tail, false, &OPENSSH_LAYOUT);
+        assert_eq!(find(&exact, &key_text("", "Doge")), Some("Doge"));
+        assert_eq!(find(&exact, &key_text("", "dOGE")), None);
+
+        let folded = dictionary("Doge\ndoge\n", 4, WordPosition::Tail, true, &OPENSSH_LAYOUT);
+        assert_eq!(folded.len(), 1);
+        assert_eq!(find(&folded, &key_text("", "dOGE")), Some("Doge"));
+    }
+
+    /// A text ending in `horse` also ends in `orse`, so only the shorter
+    /// word adds to the probability.
+    #[test]
+    fn probability_counts_each_text_once() {
+        let p = |words: &str, ignore_case: bool| {
+            dictionary(words, 4, WordPosition::Tail, ignore_case, &OPENSSH_LAYOUT)
+                .probability(&OPENSSH_LAYOUT)
+        };
+        let four = 64f64.powi(-4);
+        assert_eq!(p("orse\n", false), four);
+        assert_eq!(p("orse\nhorse\n", false), four);
+        assert_eq!(p("orse\ndoge\n", false), 2.0 * four);
+        assert_eq!(p("orse\n", true), 16.0 * four);
+    }
+}
@@ -0,0 +1,484 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use p256::elliptic_curve::group::Group;
+use p256::elliptic_curve::sec1::{ModulusSize, ToEncodedPoint};
+use p256::elliptic_curve::{Field, FieldBytes, FieldBytesSize, PrimeField};
+use primeorder::{AffinePoint, PrimeCurveParams, ProjectivePoint};
+use rand::rngs::OsRng;
+use ssh_key::EcdsaCurve;
+use ssh_key::private::{EcdsaKeypair, PrivateKey};
+use std::cell::OnceCell;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use zeroize::Zeroize;
+
+use crate::core::backend::{BatchKeyPair, KeyBackend};
+use crate::core::file_io::{KeyFileOptions, remove_openssh_key, write_openssh_key};
+use crate::core::fingerprint::{FINGERPRINT_LAYOUT, openssh_fingerprint};
+use crate::core::keypair::BATCH_SIZE;
+use crate::core::layout::{BASE64_ALPHABET, BlobByte, Layout};
+use crate::core::pattern::Pattern;
+use crate::core::pattern_set::PatternSet;
+use crate::core::secret::SecretBytes;
+
+/// Bytes of the largest scalar, the 521-bit one of nistp521.
+pub const MAX_SCALAR_LEN: usize = 66;
+
+/// NIST curve of an ECDSA key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    NistP256,
+    NistP384,
+    NistP521,
+}
+
+static NISTP256_LAYOUT: LazyLock<Layout> = LazyLock::new(|| Curve::NistP256.blob_layout());
+static NISTP384_LAYOUT: LazyLock<Layout> = LazyLock::new(|| Curve::NistP384.blob_layout());
+static NISTP521_LAYOUT: LazyLock<Layout> = LazyLock::new(|| Curve::NistP521.blob_layout());
+
+impl Curve {
+    /// The curve's name in OpenSSH key blobs, e.g. `nistp256`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Curve::NistP256 => "nistp256",
+            Curve::NistP384 => "nistp384",
+            Curve::NistP521 => "nistp521",
+        }
+    }
+
+    /// Bytes of a scalar and of each point coordinate.
+    pub fn scalar_len(&self) -> usize {
+        match self {
+            Curve::NistP256 => 32,
+            Curve::NistP384 => 48,
+            Curve::NistP521 => 66,
+        }
+    }
+
+    /// Layout of the base64 key blob, without its `=` padding.
+    pub fn layout(&self) -> &'static Layout {
+        match self {
+            Curve::NistP256 => &NISTP256_LAYOUT,
+            Curve::NistP384 => &NISTP384_LAYOUT,
+            Curve::NistP521 => &NISTP521_LAYOUT,
+        }
+    }
+
+    fn ssh_curve(&self) -> EcdsaCurve {
+        match self {
+            Curve::NistP256 => EcdsaCurve::NistP256,
+            Curve::NistP384 => EcdsaCurve::NistP384,
+            Curve::NistP521 => EcdsaCurve::NistP521,
+        }
+    }
+
+    /// The fixed start of the key blob: the key type, the curve name and the
+    /// length of the point that follows, each as an SSH string.
+    fn blob_header(&self) -> Vec<u8> {
+        let key_type = format!("ecdsa-sha2-{}", self.name());
+        let mut header = Vec::new();
+        for field in [key_type.as_bytes(), self.name().as_bytes()] {
+            header.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            header.extend_from_slice(field);
+        }
+        header.extend_from_slice(&(1 + 2 * self.scalar_len() as u32).to_be_bytes());
+        header
+    }
+
+    /// The header, the SEC1 tag of an uncompressed point and both
+    /// coordinates. The coordinates of nistp521 are 521 bits stored in 66
+    /// bytes, so their first byte only has one random bit.
+    fn blob_layout(&self) -> Layout {
+        let coordinate = || {
+            let leading = match self {
+                Curve::NistP521 => BlobByte::Masked {
+                    mask: 0xfe,
+                    value: 0,
+                },
+                _ => BlobByte::Random,
+            };
+            std::iter::once(leading)
+                .chain(std::iter::repeat_n(BlobByte::Random, self.scalar_len() - 1))
+        };
+        let blob: Vec<BlobByte> = self
+            .blob_header()
+            .into_iter()
+            .chain([SEC1_UNCOMPRESSED])
+            .map(BlobByte::Fixed)
+            .chain(coordinate())
+            .chain(coordinate())
+            .collect();
+        Layout::encoded("public key", &blob, BASE64_ALPHABET)
+    }
+}
+
+const SEC1_UNCOMPRESSED: u8 = 0x04;
+
+/// An ECDSA key pair on one of the NIST curves.
+#[derive(Debug)]
+pub struct EcdsaKeyPair {
+    pub curve: Curve,
+    /// Big-endian scalar in the first [`Curve::scalar_len`] bytes.
+    pub secret_key: SecretBytes<MAX_SCALAR_LEN>,
+    /// SEC1 uncompressed point.
+    pub public_key: Vec<u8>,
+}
+
+impl EcdsaKeyPair {
+    pub fn scalar(&self) -> &[u8] {
+        &self.secret_key.expose()[..self.curve.scalar_len()]
+    }
+
+    /// The base64 key blob as it appears in the `.pub` file, without its `=`
+    /// padding, which is what patterns match.
+    pub fn text(&self) -> String {
+        let mut blob = self.curve.blob_header();
+        blob.extend_from_slice(&self.public_key);
+        STANDARD_NO_PAD.encode(blob)
+    }
+
+    pub fn openssh_public_key(&self) -> ssh_key::public::PublicKey {
+        ssh_key::public::EcdsaPublicKey::from_sec1_bytes(&self.public_key)
+            .unwrap()
+            .into()
+    }
+
+    /// Rebuilds the key pair from its scalar for writing. Fails if the
+    /// scalar does not give the public key that was matched.
+    pub fn to_ssh_keypair(&self) -> io::Result<EcdsaKeypair> {
+        let invalid =
+            |e: p256::elliptic_curve::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+        let keypair = match self.curve {
+            Curve::NistP256 => {
+                let secret = p256::SecretKey::from_slice(self.scalar()).map_err(invalid)?;
+                EcdsaKeypair::NistP256 {
+                    public: secret.public_key().into(),
+                    private: secret.into(),
+                }
+            }
+            Curve::NistP384 => {
+                let secret = p384::SecretKey::from_slice(self.scalar()).map_err(invalid)?;
+                EcdsaKeypair::NistP384 {
+                    public: secret.public_key().into(),
+                    private: secret.into(),
+                }
+            }
+            Curve::NistP521 => {
+                let secret = p521::SecretKey::from_slice(self.scalar()).map_err(invalid)?;
+                EcdsaKeypair::NistP521 {
+                    public: secret.public_key().into(),
+                    private: secret.into(),
+                }
+            }
+        };
+        if keypair.curve() != self.curve.ssh_curve()
+            || keypair.public_key_bytes() != self.public_key.as_slice()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the ECDSA scalar does not match the public key",
+            ));
+        }
+        Ok(keypair)
+    }
+
+    /// Returns the first pattern the key pair matches.
+    pub fn find_match<'a>(&self, patterns: &'a PatternSet) -> Option<&'a Pattern> {
+        let candidate = TextCandidate::new(self);
+        let text = candidate.text.as_bytes();
+        patterns.find_match(
+            |i| text.iter().rev().nth(i).copied(),
+            |p| candidate.matches(p),
+        )
+    }
+}
+
+impl BatchKeyPair for EcdsaKeyPair {
+    fn take(&mut self) -> EcdsaKeyPair {
+        EcdsaKeyPair {
+            curve: self.curve,
+            secret_key: self.secret_key.take(),
+            public_key: std::mem::take(&mut self.public_key),
+        }
+    }
+
+    fn duplicate(&self) -> EcdsaKeyPair {
+        let mut secret_key = SecretBytes::zeroed();
+        secret_key
+            .expose_mut()
+            .copy_from_slice(self.secret_key.expose());
+        EcdsaKeyPair {
+            curve: self.curve,
+            secret_key,
+            public_key: self.public_key.clone(),
+        }
+    }
+
+    fn lock(&mut self) -> io::Result<()> {
+        self.secret_key.lock()
+    }
+}
+
+/// OpenSSH ECDSA keys on one curve. Patterns match the base64 key blob.
+#[derive(Debug, Clone, Copy)]
+pub struct EcdsaBackend {
+    pub curve: Curve,
+}
+
+impl KeyBackend for EcdsaBackend {
+    type KeyPair = EcdsaKeyPair;
+
+    fn layout(&self) -> &'static Layout {
+        self.curve.layout()
+    }
+
+    fn generate_batch(&self, batch_size: usize) -> Vec<EcdsaKeyPair> {
+        generate_ecdsa_batch(self.curve, batch_size)
+    }
+
+    fn text(&self, keypair: &EcdsaKeyPair) -> String {
+        keypair.text()
+    }
+
+    fn find_match<'a>(
+        &self,
+        keypair: &EcdsaKeyPair,
+        patterns: &'a PatternSet,
+    ) -> Option<&'a Pattern> {
+        keypair.find_match(patterns)
+    }
+
+    fn fingerprint(&self, keypair: &EcdsaKeyPair) -> String {
+        openssh_fingerprint(&keypair.openssh_public_key())
+    }
+
+    fn write(
+        &self,
+        keypair: &EcdsaKeyPair,
+        dir: &Path,
+        name: &str,
+        options: &KeyFileOptions,
+    ) -> io::Result<PathBuf> {
+        write_openssh_key(
+            PrivateKey::from(keypair.to_ssh_keypair()?),
+            dir,
+            name,
+            options,
+        )
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        remove_openssh_key(path)
+    }
+}
+
+/// An ECDSA key pair being checked against patterns, with its fingerprint
+/// computed only if a pattern needs it.
+struct TextCandidate<'a> {
+    keypair: &'a EcdsaKeyPair,
+    text: String,
+    fingerprint: OnceCell<String>,
+}
+
+impl<'a> TextCandidate<'a> {
+    fn new(keypair: &'a EcdsaKeyPair) -> Self {
+        Self {
+            keypair,
+            text: keypair.text(),
+            fingerprint: OnceCell::new(),
+        }
+    }
+
+    fn matches(&self, pattern: &Pattern) -> bool {
+        match pattern {
+            Pattern::Fingerprint(inner) => {
+                let fingerprint = self
+                    .fingerprint
+                    .get_or_init(|| openssh_fingerprint(&self.keypair.openssh_public_key()));
+                inner.matches_str(fingerprint, &FINGERPRINT_LAYOUT)
+            }
+            Pattern::And(children) => children.iter().all(|c| self.matches(c)),
+            Pattern::Or(children) => children.iter().any(|c| self.matches(c)),
+            Pattern::Not(inner) => !self.matches(inner),
+            _ => pattern.matches_str(&self.text, self.keypair.curve.layout()),
+        }
+    }
+}
+
+/// Generates `batch_size` consecutive keys `d + 1, d + 2, ...` from one
+/// random scalar `d`. The points are found in affine coordinates by adding
+/// `G, 2G, ...` to `dG`, so each candidate costs a few field multiplications
+/// and the whole batch shares one field inversion, instead of a scalar
+/// multiplication per key. ECDSA private keys are plain scalars, so the keys
+/// are as good as independently generated ones.
+pub fn generate_ecdsa_batch(curve: Curve, batch_size: usize) -> Vec<EcdsaKeyPair> {
+    match curve {
+        Curve::NistP256 => walk::<p256::NistP256>(curve, batch_size),
+        Curve::NistP384 => walk::<p384::NistP384>(curve, batch_size),
+        Curve::NistP521 => walk::<p521::NistP521>(curve, batch_size),
+    }
+}
+
+/// Affine coordinates of a point, which are the halves of its uncompressed
+/// SEC1 encoding.
+type Coordinates<C> = (
+    <C as PrimeCurveParams>::FieldElement,
+    <C as PrimeCurveParams>::FieldElement,
+);
+
+/// A curve [`walk`] can step on, with its table of steps.
+trait WalkCurve: PrimeCurveParams {
+    /// Coordinates of `G, 2G, ..., nG` for `n` = [`BATCH_SIZE`], computed
+    /// once.
+    fn steps() -> &'static [Coordinates<Self>];
+}
+
+impl WalkCurve for p256::NistP256 {
+    fn steps() -> &'static [Coordinates<Self>] {
+        static STEPS: LazyLock<Vec<Coordinates<p256::NistP256>>> =
+            LazyLock::new(steps::<p256::NistP256>);
+        &STEPS
+    }
+}
+
+impl WalkCurve for p384::NistP384 {
+    fn steps() -> &'static [Coordinates<Self>] {
+        static STEPS: LazyLock<Vec<Coordinates<p384::NistP384>>> =
+            LazyLock::new(steps::<p384::NistP384>);
+        &STEPS
+    }
+}
+
+impl WalkCurve for p521::NistP521 {
+    fn steps() -> &'static [Coordinates<Self>] {
+        static STEPS: LazyLock<Vec<Coordinates<p521::NistP521>>> =
+            LazyLock::new(steps::<p521::NistP521>);
+        &STEPS
+    }
+}
+
+fn steps<C: PrimeCurveParams>() -> Vec<Coordinates<C>>
+where
+    FieldBytesSize<C>: ModulusSize,
+    AffinePoint<C>: ToEncodedPoint<C>,
+{
+    let generator = ProjectivePoint::<C>::generator();
+    let mut point = generator;
+    let mut steps = Vec::with_capacity(BATCH_SIZE);
+    for _ in 0..BATCH_SIZE {
+        steps.push(coordinates(&point.to_affine()));
+        point += generator;
+    }
+    steps
+}
+
+fn coordinates<C: PrimeCurveParams>(point: &AffinePoint<C>) -> Coordinates<C>
+where
+    FieldBytesSize<C>: ModulusSize,
+    AffinePoint<C>: ToEncodedPoint<C>,
+{
+    let encoded = point.to_encoded_point(false);
+    let coordinate =
+        |bytes: Option<&FieldBytes<C>>| C::FieldElement::from_repr(bytes.unwrap().clone()).unwrap();
+    (coordinate(encoded.x()), coordinate(encoded.y()))
+}
+
+fn walk<C: WalkCurve>(curve: Curve, batch_size: usize) -> Vec<EcdsaKeyPair>
+where
+    FieldBytesSize<C>: ModulusSize,
+    AffinePoint<C>: ToEncodedPoint<C>,
+{
+    let mut scalar = C::Scalar::random(&mut OsRng);
+    let (mut x0, mut y0) =
+        coordinates::<C>(&(ProjectivePoint::<C>::generator() * scalar).to_affine());
+
+    let mut keypairs = Vec::with_capacity(batch_size);
+    while keypairs.len() < batch_size {
+        let steps = &C::steps()[..(batch_size - keypairs.len()).min(BATCH_SIZE)];
+        // The slopes from (x0, y0) to each step share one inversion. A zero
+        // denominator would mean dG = ±iG, so d would be one of the first
+        // few scalars or their negatives.
+        let mut denominators: Vec<C::FieldElement> = steps.iter().map(|(x, _)| *x - x0).collect();
+        batch_invert(&mut denominators).expect("random scalar next to a step");
+
+        let mut last = (x0, y0);
+        for ((x, y), inverse) in steps.iter().zip(&denominators) {
+            let slope = (*y - y0) * inverse;
+            let x3 = slope.square() - x0 - x;
+            let y3 = slope * (x0 - x3) - y0;
+            scalar += C::Scalar::ONE;
+
+            let mut secret_key = SecretBytes::zeroed();
+            let mut repr = scalar.to_repr();
+            secret_key.expose_mut()[..curve.scalar_len()].copy_from_slice(&repr);
+            repr.zeroize();
+            let mut public_key = Vec::with_capacity(1 + 2 * curve.scalar_len());
+            public_key.push(SEC1_UNCOMPRESSED);
+            public_key.extend_from_slice(&x3.to_repr());
+            public_key.extend_from_slice(&y3.to_repr());
+            keypairs.push(EcdsaKeyPair {
+                curve,
+                secret_key,
+                public_key,
+            });
+            last = (x3, y3);
+        }
+        (x0, y0) = last;
+    }
+    scalar.zeroize();
+    keypairs
+}
+
+/// Inverts every value with a single field inversion (Montgomery's trick),
+/// or returns `None` if one of them is zero.
+fn batch_invert<F: Field>(values: &mut [F]) -> Option<()> {
+    let mut prefixes = Vec::with_capacity(values.len());
+    let mut product = F::ONE;
+    for value in values.iter() {
+        prefixes.push(product);
+        product *= value;
+    }
+    let mut inverse = Option::<F>::from(product.invert())?;
+    for (value, prefix) in values.iter_mut().zip(prefixes).rev() {
+        let rest = inverse * *value;
+        *value = inverse * prefix;
+        inverse = rest;
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walked_keys_match_their_scalars() {
+        // Past the table of steps, so the walk carries on from where the
+        // first pass ended.
+        for (curve, batch_size) in [
+            (Curve::NistP256, 2 * BATCH_SIZE + 3),
+            (Curve::NistP384, 3),
+            (Curve::NistP521, 3),
+        ] {
+            let keypairs = generate_ecdsa_batch(curve, batch_size);
+            assert_eq!(keypairs.len(), batch_size);
+            for keypair in &keypairs {
+                assert!(keypair.to_ssh_keypair().is_ok(), "{:?}", curve);
+            }
+        }
+    }
+
+    #[test]
+    fn batch_invert_inverts_every_value() {
+        let values: Vec<p256::Scalar> = (1..=5u64).map(p256::Scalar::from).collect();
+        let mut inverses = values.clone();
+        batch_invert(&mut inverses).unwrap();
+        for (value, inverse) in values.iter().zip(&inverses) {
+            assert_eq!(*value * inverse, p256::Scalar::ONE);
+        }
+
+        let mut with_zero = vec![p256::Scalar::ONE, p256::Scalar::ZERO];
+        assert!(batch_invert(&mut with_zero).is_none());
+    }
+}
@@ -0,0 +1,549 @@
+use std::collections::BTreeMap;
+
+use crate::core::fingerprint::FINGERPRINT_LAYOUT;
+use crate::core::layout::{CharSet, Layout};
+use crate::core::mask::KeyMask;
+use crate::core::pattern::{Pattern, PatternError};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Leaf(String),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::And => "AND".to_string(),
+            Token::Or => "OR".to_string(),
+            Token::Not => "NOT".to_string(),
+            Token::Open => "'('".to_string(),
+            Token::Close => "')'".to_string(),
+            Token::Leaf(leaf) => format!("'{}'", leaf),
+        }
+    }
+}
+
+/// A token and the byte range of the input it was read from.
+#[derive(Debug, Clone)]
+struct Spanned {
+    token: Token,
+    start: usize,
+    end: usize,
+}
+
+/// Prefixes after which a regex runs to the end of the input, since it has
+/// no closing delimiter.
+const OPEN_REGEX_PREFIXES: [&str; 2] = ["re:", "fp:re:"];
+
+/// Parses `input` as a boolean expression of patterns, e.g.
+/// `prefix:dev & ops` or `yee AND NOT fp:contains:fuck`. Operators are `&`,
+/// `|`, `!` or the upper case keywords `AND`, `OR`, `NOT`, with the usual
+/// precedence and parentheses for grouping. Returns `None` if `input` is a
+/// single pattern, which is left to `parse_leaf` as a whole, so a lone
+/// `AND` is still a suffix.
+///
+/// Syntax errors from `parse_leaf` are moved to where the leaf is in
+/// `input`.
+pub fn parse_expression(
+    input: &str,
+    parse_leaf: impl Fn(&str) -> Result<Pattern, PatternError>,
+) -> Result<Option<Pattern>, PatternError> {
+    let tokens = tokenize(input);
+    if tokens.len() <= 1 {
+        return Ok(None);
+    }
+
+    let mut parser = Parser {
+        input,
+        tokens,
+        position: 0,
+        parse_leaf,
+    };
+    let pattern = parser.parse_or()?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(Some(pattern)),
+        Some(spanned) => {
+            let message = match &spanned.token {
+                Token::Leaf(_) | Token::Not | Token::Open => {
+                    format!("expected '&' or '|' before {}", spanned.token.describe())
+                }
+                token => format!("unexpected {}", token.describe()),
+            };
+            Err(PatternError::syntax(
+                input,
+                spanned.start..spanned.end,
+                message,
+            ))
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Vec<Spanned> {
+    let chars: Vec<(usize, char)> = input.char_indices().collect();
+    let offset = |i: usize| chars.get(i).map_or(input.len(), |&(offset, _)| offset);
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let token = match chars[i].1 {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '&' => Token::And,
+            '|' => Token::Or,
+            '!' => Token::Not,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            _ => {
+                let start = i;
+                let mut in_regex = false;
+                while i < chars.len() {
+                    let before = &input[offset(start)..offset(i)];
+                    if OPEN_REGEX_PREFIXES.contains(&before) {
+                        i = chars.len();
+                        break;
+                    }
+                    let c = chars[i].1;
+                    if in_regex {
+                        match c {
+                            '\\' => i += 1,
+                            '/' => in_regex = false,
+                            _ => {}
+                        }
+                    } else if c.is_whitespace() || "&|!()".contains(c) {
+                        break;
+                    } else if c == '/' && (before.is_empty() || before == "fp:") {
+                        // Operators inside a regex belong to the regex.
+                        in_regex = true;
+                    }
+                    i += 1;
+                }
+                let (start, end) = (offset(start), offset(i));
+                let leaf = input[start..end].trim_end();
+                let token = match leaf {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Leaf(leaf.to_string()),
+                };
+                tokens.push(Spanned {
+                    token,
+                    start,
+                    end: start + leaf.len(),
+                });
+                continue;
+            }
+        };
+        tokens.push(Spanned {
+            token,
+            start: offset(i),
+            end: offset(i + 1),
+        });
+        i += 1;
+    }
+
+    tokens
+}
+
+struct Parser<'a, F> {
+    input: &'a str,
+    tokens: Vec<Spanned>,
+    position: usize,
+    parse_leaf: F,
+}
+
+impl<F: Fn(&str) -> Result<Pattern, PatternError>> Parser<'_, F> {
+    fn next_is(&mut self, token: &Token) -> bool {
+        if self
+            .tokens
+            .get(self.position)
+            .is_some_and(|spanned| spanned.token == *token)
+        {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Pattern, PatternError> {
+        let mut children = vec![self.parse_and()?];
+        while self.next_is(&Token::Or) {
+            children.push(self.parse_and()?);
+        }
+        Ok(match children.len() {
+            1 => children.pop().unwrap(),
+            _ => Pattern::Or(children),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Pattern, PatternError> {
+        let mut children = vec![self.parse_not()?];
+        while self.next_is(&Token::And) {
+            children.push(self.parse_not()?);
+        }
+        Ok(match children.len() {
+            1 => children.pop().unwrap(),
+            _ => Pattern::And(children),
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<Pattern, PatternError> {
+        let end = self.input.len();
+        let Some(spanned) = self.tokens.get(self.position).cloned() else {
+            return Err(PatternError::syntax(
+                self.input,
+                end..end,
+                "expected a pattern after this".to_string(),
+            ));
+        };
+        self.position += 1;
+        match spanned.token {
+            Token::Not => Ok(Pattern::Not(Box::new(self.parse_not()?))),
+            Token::Open => {
+                let pattern = self.parse_or()?;
+                if !self.next_is(&Token::Close) {
+                    return Err(PatternError::syntax(
+                        self.input,
+                        spanned.start..spanned.end,
+                        "this '(' is never closed".to_string(),
+                    ));
+                }
+                Ok(pattern)
+            }
+            Token::Leaf(leaf) => (self.parse_leaf)(&leaf).map_err(|error| match error {
+                PatternError::Syntax { span, message, .. } => PatternError::syntax(
+                    self.input,
+                    spanned.start + span.start..spanned.start + span.end,
+                    message,
+                ),
+                error => error,
+            }),
+            token => Err(PatternError::syntax(
+                self.input,
+                spanned.start..spanned.end,
+                format!("expected a pattern, found {}", token.describe()),
+            )),
+        }
+    }
+}
+
+/// Character positions a pattern reads, per text. `Some` is a bit set with
+/// as many words as the highest position needs, so texts of any length fit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Positions {
+    Some(Vec<u64>),
+    All,
+}
+
+impl Positions {
+    const NONE: Self = Positions::Some(Vec::new());
+
+    fn range(start: usize, len: usize) -> Self {
+        Self::of(start..start + len)
+    }
+
+    fn of(positions: impl IntoIterator<Item = usize>) -> Self {
+        let mut words = Vec::new();
+        for position in positions {
+            if words.len() <= position / 64 {
+                words.resize(position / 64 + 1, 0u64);
+            }
+            words[position / 64] |= 1 << (position % 64);
+        }
+        Positions::Some(words)
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self, Positions::Some(words) if words.iter().all(|&word| word == 0))
+    }
+
+    fn union(self, other: Self) -> Self {
+        match (self, other) {
+            (Positions::Some(mut a), Positions::Some(b)) => {
+                if a.len() < b.len() {
+                    a.resize(b.len(), 0);
+                }
+                a.iter_mut().zip(&b).for_each(|(a, b)| *a |= b);
+                Positions::Some(a)
+            }
+            _ => Positions::All,
+        }
+    }
+
+    fn is_disjoint(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Positions::Some(a), Positions::Some(b)) => a.iter().zip(b).all(|(a, b)| a & b == 0),
+            (positions, Positions::All) | (Positions::All, positions) => positions.is_empty(),
+        }
+    }
+}
+
+/// The positions a pattern reads from the key text and from the
+/// fingerprint. Patterns reading disjoint positions are independent, since
+/// the characters of a layout are independent and the fingerprint is a hash
+/// of the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Footprint {
+    key: Positions,
+    fingerprint: Positions,
+}
+
+impl Footprint {
+    const EMPTY: Self = Footprint {
+        key: Positions::NONE,
+        fingerprint: Positions::NONE,
+    };
+
+    fn of(pattern: &Pattern, layout: &Layout) -> Self {
+        let key = match pattern {
+            Pattern::Suffix(text, _) => {
+                Positions::range(layout.len().saturating_sub(text.len()), text.len())
+            }
+            Pattern::FoldedSuffix(text, _, _) => {
+                let len = text.chars().count();
+                Positions::range(layout.len().saturating_sub(len), len)
+            }
+            Pattern::Prefix(text, _) => Positions::range(layout.variable_start(), text.len()),
+            Pattern::At(offset, text, _) => Positions::range(*offset, text.len()),
+            // The key bytes show through in every character of the text.
+            Pattern::Hex(..) => Positions::All,
+            Pattern::Contains(_) | Pattern::Regex(_) | Pattern::Dictionary(_) => Positions::All,
+            Pattern::Fingerprint(inner) => {
+                return Footprint {
+                    key: Positions::NONE,
+                    fingerprint: Footprint::of(inner, &FINGERPRINT_LAYOUT).key,
+                };
+            }
+            Pattern::Not(inner) => return Footprint::of(inner, layout),
+            Pattern::And(children) | Pattern::Or(children) => {
+                return children
+                    .iter()
+                    .map(|child| Footprint::of(child, layout))
+                    .fold(Footprint::EMPTY, Footprint::union);
+            }
+        };
+        Footprint {
+            key,
+            fingerprint: Positions::NONE,
+        }
+    }
+
+    fn union(self, other: Self) -> Self {
+        Footprint {
+            key: self.key.union(other.key),
+            fingerprint: self.fingerprint.union(other.fingerprint),
+        }
+    }
+
+    fn is_disjoint(&self, other: &Self) -> bool {
+        self.key.is_disjoint(&other.key) && self.fingerprint.is_disjoint(&other.fingerprint)
+    }
+}
+
+fn pairwise_independent(children: &[Pattern], layout: &Layout) -> bool {
+    let footprints: Vec<Footprint> = children
+        .iter()
+        .map(|child| Footprint::of(child, layout))
+        .collect();
+    pairwise_disjoint(&footprints)
+}
+
+fn pairwise_disjoint(footprints: &[Footprint]) -> bool {
+    footprints
+        .iter()
+        .enumerate()
+        .all(|(i, a)| footprints[i + 1..].iter().all(|b| a.is_disjoint(b)))
+}
+
+/// The characters a literal pattern requires at each position of the key
+/// text, if it is one.
+fn literal_classes(pattern: &Pattern, layout: &Layout) -> Option<Vec<(usize, CharSet)>> {
+    let singletons = |offset: usize, text: &str| {
+        text.bytes()
+            .enumerate()
+            .map(|(i, c)| {
+                let mut class = CharSet::default();
+                class.insert(c);
+                (offset + i, class)
+            })
+            .collect()
+    };
+    match pattern {
+        Pattern::Suffix(text, _) => Some(singletons(layout.len().saturating_sub(text.len()), text)),
+        Pattern::Prefix(text, _) => Some(singletons(layout.variable_start(), text)),
+        Pattern::At(offset, text, _) => Some(singletons(*offset, text)),
+        Pattern::FoldedSuffix(text, folding, _) => {
+            let classes = folding.classes(text);
+            let offset = layout.len().saturating_sub(classes.len());
+            Some(
+                classes
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, class)| (offset + i, class))
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Whether two literal patterns need different characters at a common
+/// position, or two hex patterns different bits, so they can never both
+/// match.
+fn mutually_exclusive(a: &Pattern, b: &Pattern, layout: &Layout) -> bool {
+    if let (Pattern::Hex(_, a), Pattern::Hex(_, b)) = (a, b) {
+        return a.combine(b).is_none();
+    }
+    let (Some(a), Some(b)) = (literal_classes(a, layout), literal_classes(b, layout)) else {
+        return false;
+    };
+    a.iter().any(|(position, class_a)| {
+        b.iter()
+            .any(|(other, class_b)| position == other && class_a.intersection(class_b).is_empty())
+    })
+}
+
+fn pairwise_exclusive(children: &[Pattern], layout: &Layout) -> bool {
+    children.iter().enumerate().all(|(i, a)| {
+        children[i + 1..]
+            .iter()
+            .all(|b| mutually_exclusive(a, b, layout))
+    })
+}
+
+/// Probability that all `children` match, and whether it is exact. The
+/// literal children are merged into one set of allowed characters per
+/// position, and the hex children into one mask, which is exact however
+/// they overlap. The rest are exact when they read positions disjoint from
+/// each other and from the literals, and are otherwise treated as
+/// independent.
+pub fn and_probability(children: &[Pattern], layout: &Layout) -> Option<(f64, bool)> {
+    let mut required: BTreeMap<usize, CharSet> = BTreeMap::new();
+    let mut hex: Option<KeyMask> = None;
+    let mut others = Vec::new();
+    for child in children {
+        if let Pattern::Hex(_, mask) = child {
+            match hex.map_or(Some(*mask), |hex| hex.combine(mask)) {
+                Some(combined) => hex = Some(combined),
+                None => return Some((0.0, true)),
+            }
+            continue;
+        }
+        match literal_classes(child, layout) {
+            Some(classes) => {
+                for (position, class) in classes {
+                    required
+                        .entry(position)
+                        .and_modify(|required| *required = required.intersection(&class))
+                        .or_insert(class);
+                }
+            }
+            None => others.push(child),
+        }
+    }
+
+    let literal_probability: f64 = required
+        .iter()
+        .map(|(&position, class)| {
+            if position >= layout.len() {
+                return 0.0;
+            }
+            let allowed = layout.position(position);
+            class.intersection(&allowed).len() as f64 / allowed.len() as f64
+        })
+        .product();
+    if literal_probability == 0.0 {
+        return Some((0.0, true));
+    }
+
+    let mut estimates = others
+        .iter()
+        .map(|child| child.estimate(layout))
+        .collect::<Option<Vec<_>>>()?;
+    let mut footprints: Vec<Footprint> = others
+        .iter()
+        .map(|child| Footprint::of(child, layout))
+        .collect();
+    if let Some(hex) = hex {
+        estimates.push((0.5f64.powi(hex.fixed_bits() as i32), true));
+        footprints.push(Footprint {
+            key: Positions::All,
+            fingerprint: Positions::NONE,
+        });
+    }
+    let all_exact = estimates.iter().all(|&(_, exact)| exact);
+
+    footprints.push(Footprint {
+        key: Positions::of(required.keys().copied()),
+        fingerprint: Positions::NONE,
+    });
+
+    let product = estimates.iter().map(|&(p, _)| p).product::<f64>() * literal_probability;
+    Some((product, all_exact && pairwise_disjoint(&footprints)))
+}
+
+/// Probability that any of `children` matches, and whether it is exact. It
+/// is exact when the children read disjoint positions or are literals that
+/// exclude each other; otherwise they are treated as independent.
+pub fn or_probability(children: &[Pattern], layout: &Layout) -> Option<(f64, bool)> {
+    let estimates = children
+        .iter()
+        .map(|child| child.estimate(layout))
+        .collect::<Option<Vec<_>>>()?;
+    let all_exact = estimates.iter().all(|&(_, exact)| exact);
+
+    if pairwise_exclusive(children, layout) {
+        let sum = estimates.iter().map(|&(p, _)| p).sum();
+        return Some((sum, all_exact));
+    }
+
+    let miss: f64 = estimates.iter().map(|&(p, _)| 1.0 - p).product();
+    Some((
+        1.0 - miss,
+        all_exact && pairwise_independent(children, layout),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::layout::{BASE64_ALPHABET, BlobByte};
+
+    /// 160 random base64 characters, longer than the 128 positions a single
+    /// `u128` footprint could hold.
+    fn long_layout() -> Layout {
+        Layout::encoded("test", &[BlobByte::Random; 120], BASE64_ALPHABET)
+    }
+
+    fn pattern(text: &str, layout: &Layout) -> Pattern {
+        Pattern::new_for_layout(text.to_string(), layout).unwrap()
+    }
+
+    #[test]
+    fn overlapping_parts_past_position_128_are_approximate() {
+        let layout = long_layout();
+        assert_eq!(layout.len(), 160);
+        for text in ["abc & /abc$/", "abc & contains:abc", "abc | /abc$/"] {
+            assert!(
+                !pattern(text, &layout).is_probability_exact(&layout),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn disjoint_parts_past_position_128_stay_exact() {
+        let layout = long_layout();
+        let both = pattern("at:130:ab & xyz", &layout);
+        assert!(both.is_probability_exact(&layout));
+        assert_eq!(both.probability(&layout), Some(64f64.powi(-5)));
+    }
+}
@@ -1,63 +1,452 @@
-use crate::core::keypair::KeyPair;
-use ssh_key::LineEnding;
+use crate::core::backend::KeyBackend;
+use crate::core::keypair::{Ed25519KeyPair, Secret};
+use rand::RngCore;
+use rand::rngs::OsRng;
 use ssh_key::private::{Ed25519Keypair, PrivateKey};
-use std::fs::{self, OpenOptions};
-use std::io::Write;
-use std::path::Path;
+use ssh_key::{Cipher, Kdf, LineEnding};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
 
 #[cfg(unix)]
-use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
 
-pub fn save_keypair_to_files(keypair: &KeyPair, filename: &str) -> std::io::Result<()> {
-    create_out_directory()?;
+/// Passphrase protection for written OpenSSH private keys, the same as
+/// `ssh-keygen` uses: aes256-ctr with a key derived by bcrypt-pbkdf.
+#[derive(Clone)]
+pub struct KeyEncryption {
+    pub passphrase: Zeroizing<String>,
+    pub kdf_rounds: u32,
+}
 
-    let ed25519_keypair = create_ssh_keypair_from_ed25519_keys(keypair);
-    let private_key = PrivateKey::from(ed25519_keypair);
+impl fmt::Debug for KeyEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyEncryption")
+            .field("passphrase", &"(redacted)")
+            .field("kdf_rounds", &self.kdf_rounds)
+            .finish()
+    }
+}
 
-    write_public_key_to_file(&private_key, &filename)?;
-    write_private_key_to_file(&private_key, &filename)?;
+/// Length of the random bcrypt-pbkdf salt, as chosen by `ssh-keygen`.
+const KDF_SALT_LEN: usize = 16;
 
-    Ok(())
+/// Default for [`KeyWriter::new`]'s `name_template`, which gives names like
+/// `yee_1700000000`.
+pub const DEFAULT_NAME_TEMPLATE: &str = "{pattern}_{timestamp}";
+
+const PLACEHOLDERS: [&str; 4] = ["pattern", "fingerprint", "timestamp", "n"];
+
+/// What [`KeyBackend::write`] may need besides the key pair: the comment and
+/// the passphrase protection of OpenSSH keys.
+#[derive(Debug, Clone)]
+pub struct KeyFileOptions {
+    pub comment: String,
+    pub encryption: Option<KeyEncryption>,
 }
 
-fn create_out_directory() -> std::io::Result<()> {
-    if !Path::new("out").exists() {
-        fs::create_dir("out")?;
-        #[cfg(unix)]
-        {
-            fs::set_permissions("out", fs::Permissions::from_mode(0o700))?;
+/// Writes found keys into a directory, named after a template. Names are
+/// made unique by appending `_2`, `_3`, ..., so an existing key is never
+/// overwritten.
+#[derive(Debug)]
+pub struct KeyWriter<B: KeyBackend> {
+    backend: B,
+    dir: PathBuf,
+    name_template: String,
+    options: KeyFileOptions,
+    n_saved: u64,
+}
+
+impl<B: KeyBackend> KeyWriter<B> {
+    /// `name_template` may use `{pattern}` (what the key was found for),
+    /// `{fingerprint}` (the SHA256 fingerprint, the onion address or the
+    /// WireGuard public key),
+    /// `{timestamp}` (Unix seconds) and `{n}` (keys saved so far, from 1).
+    pub fn new(
+        backend: B,
+        dir: PathBuf,
+        name_template: String,
+        comment: String,
+        encryption: Option<KeyEncryption>,
+    ) -> Result<Self, String> {
+        check_name_template(&name_template)?;
+        Ok(Self {
+            backend,
+            dir,
+            name_template,
+            options: KeyFileOptions {
+                comment,
+                encryption,
+            },
+            n_saved: 0,
+        })
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Saves the key pair under a fresh name made from `pattern_name`, and
+    /// returns the path [`KeyBackend::write`] returned.
+    pub fn save(&mut self, keypair: &B::KeyPair, pattern_name: &str) -> io::Result<PathBuf> {
+        create_out_directory(&self.dir)?;
+        let name = self.render_name(&self.backend.fingerprint(keypair), pattern_name);
+        let path = self
+            .backend
+            .write(keypair, &self.dir, &name, &self.options)?;
+        self.n_saved += 1;
+        Ok(path)
+    }
+
+    /// Removes the files of a key written by [`KeyWriter::save`].
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        self.backend.remove(path)
+    }
+
+    /// `fingerprint` is the SHA256 fingerprint, the onion address or the
+    /// WireGuard public key.
+    fn render_name(&self, fingerprint: &str, pattern_name: &str) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.name_template
+            .replace("{pattern}", &sanitize(pattern_name))
+            // Base64 fingerprints may contain '/'.
+            .replace(
+                "{fingerprint}",
+                &fingerprint.replace('/', "_").replace('+', "-"),
+            )
+            .replace("{timestamp}", &timestamp.to_string())
+            .replace("{n}", &(self.n_saved + 1).to_string())
+    }
+}
+
+/// Writes an OpenSSH key pair as `dir/{name}` and `dir/{name}.pub`, for the
+/// backends of SSH keys.
+pub fn write_openssh_key(
+    mut private_key: PrivateKey,
+    dir: &Path,
+    name: &str,
+    options: &KeyFileOptions,
+) -> io::Result<PathBuf> {
+    private_key.set_comment(&options.comment);
+    if let Some(encryption) = &options.encryption {
+        private_key = encrypt_private_key(&private_key, encryption)?;
+    }
+    let pem = private_key.to_openssh(LineEnding::LF).unwrap();
+    let public_key = format!("{}\n", private_key.public_key().to_openssh().unwrap());
+
+    for candidate in unique_names(name) {
+        let path = dir.join(&candidate);
+        let Some(mut private_file) = create_new(&path, 0o600)? else {
+            continue;
+        };
+        let Some(mut public_file) = create_new(&public_key_path(&path), 0o644)? else {
+            drop(private_file);
+            fs::remove_file(&path)?;
+            continue;
+        };
+        private_file.write_all(pem.as_bytes())?;
+        public_file.write_all(public_key.as_bytes())?;
+        return Ok(path);
+    }
+    unreachable!("unique_names never ends")
+}
+
+/// Removes a key pair written by [`write_openssh_key`].
+pub fn remove_openssh_key(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)?;
+    fs::remove_file(public_key_path(path))
+}
+
+/// Checks that `template` only uses known placeholders and names a file in
+/// the output directory rather than a path.
+fn check_name_template(template: &str) -> Result<(), String> {
+    if template.contains(['/', '\\']) {
+        return Err(format!(
+            "'{}' is a path, the directory is set with --out-dir",
+            template
+        ));
+    }
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}') else {
+            return Err(format!("'{}' has a '{{' without a '}}'", template));
+        };
+        let placeholder = &rest[open + 1..open + close];
+        if !PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "unknown placeholder '{{{}}}', expected one of {{pattern}}, {{fingerprint}}, \
+                 {{timestamp}} or {{n}}",
+                placeholder
+            ));
         }
+        rest = &rest[open + close + 1..];
+    }
+    if template.is_empty() {
+        return Err("the name template is empty".to_string());
     }
     Ok(())
 }
 
-fn create_ssh_keypair_from_ed25519_keys(keypair: &KeyPair) -> Ed25519Keypair {
-    let mut key_bytes = [0u8; 64];
-    key_bytes[..32].copy_from_slice(&keypair.secret_key);
-    key_bytes[32..].copy_from_slice(&keypair.public_key.to_bytes());
-    Ed25519Keypair::from_bytes(&key_bytes).unwrap()
+/// Makes text usable in a file name. Suffixes may contain '/', which is a
+/// base64 character.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
 }
 
-fn write_public_key_to_file(private_key: &PrivateKey, filename: &str) -> std::io::Result<()> {
-    let public_key = private_key.public_key();
-    let filename = format!("out/{}.pub", filename);
-    std::fs::write(filename, public_key.to_string())
+/// `name`, then `name_2`, `name_3` and so on.
+pub fn unique_names(name: &str) -> impl Iterator<Item = String> + '_ {
+    std::iter::once(name.to_string()).chain((2..).map(move |i| format!("{}_{}", name, i)))
 }
 
-fn write_private_key_to_file(private_key: &PrivateKey, suffix: &str) -> std::io::Result<()> {
-    let pem = private_key.to_openssh(LineEnding::LF).unwrap();
-    let filename = format!("out/{}", suffix);
-    {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&filename)?;
-        file.write_all(pem.as_bytes())?;
+/// Creates `dir/{name}` under the first of the unique names for `name` that
+/// is still free, for keys that are written as a directory of files. Only
+/// its owner can enter it, which Tor also insists on for onion services.
+pub fn create_unique_dir(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    builder.mode(0o700);
+    unique_names(name)
+        .map(|candidate| dir.join(candidate))
+        .find_map(|path| match builder.create(&path) {
+            Ok(()) => Some(Ok(path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => None,
+            Err(e) => Some(Err(e)),
+        })
+        .unwrap()
+}
+
+pub fn public_key_path(private_key_path: &Path) -> PathBuf {
+    let mut path = private_key_path.as_os_str().to_owned();
+    path.push(".pub");
+    PathBuf::from(path)
+}
+
+/// Creates a file that must not exist yet, or returns `None` if it does.
+fn create_new(path: &Path, mode: u32) -> io::Result<Option<File>> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(mode);
+    #[cfg(not(unix))]
+    let _ = mode;
+    match options.open(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(None),
+        Err(e) => Err(e),
     }
+}
+
+fn encrypt_private_key(
+    private_key: &PrivateKey,
+    encryption: &KeyEncryption,
+) -> std::io::Result<PrivateKey> {
+    let mut salt = vec![0u8; KDF_SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let kdf = Kdf::Bcrypt {
+        salt,
+        rounds: encryption.kdf_rounds,
+    };
+    private_key
+        .encrypt_with(
+            Cipher::Aes256Ctr,
+            kdf,
+            OsRng.next_u32(),
+            encryption.passphrase.as_bytes(),
+        )
+        .map_err(std::io::Error::other)
+}
+
+/// Writes a new file only its owner can read. It is created with those
+/// permissions, so the secret is never readable by anyone else, and an
+/// existing file is an error rather than overwritten.
+pub fn write_secret_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let Some(mut file) = create_new(path, 0o600)? else {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("'{}' already exists", path.display()),
+        ));
+    };
+    file.write_all(contents)
+}
+
+/// Creates `dir` and any missing parents private to their owner, with no
+/// moment in which another user could enter them.
+fn create_out_directory(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
     #[cfg(unix)]
-    {
-        fs::set_permissions(&filename, fs::Permissions::from_mode(0o600))?;
+    builder.mode(0o700);
+    builder.create(dir)
+}
+
+pub fn create_ssh_keypair_from_ed25519_keys(
+    keypair: &Ed25519KeyPair,
+) -> std::io::Result<Ed25519Keypair> {
+    // OpenSSH stores the seed and re-derives the scalar from it, so a key
+    // that only exists as an expanded scalar cannot be written.
+    let Secret::Seed(seed) = &keypair.secret_key else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "OpenSSH keys must be generated from a seed",
+        ));
+    };
+    let mut key_bytes = Zeroizing::new([0u8; 64]);
+    key_bytes[..32].copy_from_slice(seed.expose());
+    key_bytes[32..].copy_from_slice(&keypair.public_key.to_bytes());
+    Ok(Ed25519Keypair::from_bytes(&key_bytes).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::keypair::{Ed25519Backend, Strategy};
+
+    const BACKEND: Ed25519Backend = Ed25519Backend {
+        strategy: Strategy::Seed,
+    };
+
+    fn out_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "vanity-ssh-rs-file-io-{}-{}",
+            test,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn writer(dir: PathBuf, template: &str) -> KeyWriter<Ed25519Backend> {
+        KeyWriter::new(
+            BACKEND,
+            dir,
+            template.to_string(),
+            "test@vanity".to_string(),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn known_placeholders_are_accepted() {
+        for template in [
+            DEFAULT_NAME_TEMPLATE,
+            "{pattern}-{fingerprint}-{n}",
+            "key",
+            "id_{pattern}{pattern}",
+        ] {
+            assert_eq!(check_name_template(template), Ok(()), "{}", template);
+        }
+    }
+
+    #[test]
+    fn bad_templates_are_rejected() {
+        assert_eq!(
+            check_name_template("{pattern}_{date}").unwrap_err(),
+            "unknown placeholder '{date}', expected one of {pattern}, {fingerprint}, \
+             {timestamp} or {n}"
+        );
+        assert_eq!(
+            check_name_template("{pattern").unwrap_err(),
+            "'{pattern' has a '{' without a '}'"
+        );
+        assert!(check_name_template("{}").is_err());
+        assert_eq!(
+            check_name_template("keys/{pattern}").unwrap_err(),
+            "'keys/{pattern}' is a path, the directory is set with --out-dir"
+        );
+        assert!(check_name_template("..\\{pattern}").is_err());
+        assert_eq!(
+            check_name_template("").unwrap_err(),
+            "the name template is empty"
+        );
+        assert!(
+            KeyWriter::new(BACKEND, out_dir("bad"), "{x}".into(), String::new(), None).is_err()
+        );
+    }
+
+    #[test]
+    fn names_never_leave_the_directory() {
+        assert_eq!(sanitize("ab/c\\d"), "ab_c_d");
+        assert_eq!(sanitize("a\nb\tc"), "a_b_c");
+        assert_eq!(sanitize("dév+0"), "dév+0");
+    }
+
+    #[test]
+    fn placeholders_are_rendered() {
+        let named = writer(out_dir("render"), "{pattern}-{fingerprint}-{n}");
+        assert_eq!(
+            named.render_name("SHA256:ab/c+d", "x/y"),
+            "x_y-SHA256:ab_c-d-1"
+        );
+
+        let timestamped = writer(out_dir("render"), "{timestamp}");
+        let name = timestamped.render_name("", "");
+        assert!(
+            !name.is_empty() && name.bytes().all(|c| c.is_ascii_digit()),
+            "{}",
+            name
+        );
+    }
+
+    #[test]
+    fn unique_names_count_up() {
+        let names: Vec<String> = unique_names("key").take(3).collect();
+        assert_eq!(names, ["key", "key_2", "key_3"]);
+    }
+
+    #[test]
+    fn colliding_names_get_a_number() {
+        let dir = out_dir("collide");
+        let mut writer = writer(dir.clone(), "{pattern}");
+        let keypairs = BACKEND.generate_batch(3);
+        let paths: Vec<PathBuf> = keypairs
+            .iter()
+            .map(|keypair| writer.save(keypair, "dev").unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            [dir.join("dev"), dir.join("dev_2"), dir.join("dev_3")]
+        );
+        assert!(public_key_path(&paths[2]).exists());
+
+        assert_eq!(create_unique_dir(&dir, "dev").unwrap(), dir.join("dev_4"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn existing_files_are_not_overwritten() {
+        let dir = out_dir("existing");
+        create_out_directory(&dir).unwrap();
+        let path = dir.join("key");
+        fs::write(&path, "old").unwrap();
+
+        assert!(create_new(&path, 0o600).unwrap().is_none());
+        let error = write_secret_file(&path, b"new").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+
+        let new = dir.join("other");
+        assert!(create_new(&new, 0o600).unwrap().is_some());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                fs::metadata(&new).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+        }
+        fs::remove_dir_all(dir).unwrap();
     }
-    Ok(())
 }
@@ -0,0 +1,28 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use sha2::{Digest, Sha256};
+use ssh_key::HashAlg;
+use std::sync::LazyLock;
+
+use crate::core::layout::{BASE64_ALPHABET, BlobByte, Layout};
+
+const FINGERPRINT_PREFIX: &str = "SHA256:";
+
+/// Layout of the unpadded base64 SHA256 digest in an OpenSSH fingerprint.
+pub static FINGERPRINT_LAYOUT: LazyLock<Layout> =
+    LazyLock::new(|| Layout::encoded("fingerprint", &[BlobByte::Random; 32], BASE64_ALPHABET));
+
+/// The SHA256 fingerprint as printed by `ssh-keygen -lf`, without the
+/// `SHA256:` prefix.
+pub fn openssh_fingerprint(public_key: &ssh_key::public::PublicKey) -> String {
+    let fingerprint = public_key.fingerprint(HashAlg::Sha256).to_string();
+    fingerprint
+        .strip_prefix(FINGERPRINT_PREFIX)
+        .unwrap_or(&fingerprint)
+        .to_string()
+}
+
+/// [`openssh_fingerprint`] of a raw key blob, without parsing it first.
+pub fn blob_fingerprint(blob: &[u8]) -> String {
+    STANDARD_NO_PAD.encode(Sha256::digest(blob))
+}
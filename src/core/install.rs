@@ -0,0 +1,308 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+
+use crate::core::file_io::{public_key_path, unique_names};
+
+/// Where `--install` puts the key unless told otherwise, under the file name
+/// `ssh-keygen` uses for the key type.
+pub const DEFAULT_INSTALL_DIR: &str = "~/.ssh";
+
+/// The OpenSSH client config `--ssh-host` adds its `Host` block to.
+pub const SSH_CONFIG: &str = "~/.ssh/config";
+
+/// What [`install_key_pair`] did.
+pub struct Installed {
+    pub path: PathBuf,
+    /// Where the key pair that was there before was moved to.
+    pub backup: Option<PathBuf>,
+}
+
+/// Expands a leading `~` to the home directory.
+pub fn expand_home(path: &Path) -> io::Result<PathBuf> {
+    let Ok(rest) = path.strip_prefix("~") else {
+        return Ok(path.to_path_buf());
+    };
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cannot find the home directory"))?;
+    Ok(PathBuf::from(home).join(rest))
+}
+
+/// Checks what [`install_key_pair`] and [`add_host_block`] check again once
+/// a key is found: that `path`, `path.pub`, their directory and the
+/// `config` to add a `Host` block to belong to the current user and can be
+/// written. Run before the search, so a key that could never be installed
+/// fails at once rather than hours into the search.
+pub fn check_install_target(path: &Path, config: Option<&Path>) -> io::Result<()> {
+    let dir = install_dir(path);
+    let public_path = public_key_path(path);
+    for checked in [dir.as_path(), path, public_path.as_path()]
+        .into_iter()
+        .chain(config)
+    {
+        check_owner(checked)?;
+    }
+    // Existing keys are renamed, so only the directory has to be writable.
+    check_writable(&dir)?;
+    if let Some(config) = config {
+        check_writable(config)?;
+    }
+    Ok(())
+}
+
+/// Copies the key pair saved at `saved` (and `saved.pub`) to `path` (and
+/// `path.pub`), as `ssh-keygen -f path` would have written it. A key pair
+/// already at `path` is first renamed to `path-{timestamp}`, so it stays a
+/// usable pair. The new files are written next to their destination and
+/// renamed into place, so `path` never holds a partly written key.
+///
+/// Refuses to touch files or a directory owned by another user, which
+/// usually means running under `sudo` with someone else's home.
+pub fn install_key_pair(saved: &Path, path: &Path) -> io::Result<Installed> {
+    let private_key = Zeroizing::new(fs::read(saved)?);
+    let public_key = fs::read(public_key_path(saved))?;
+    let public_path = public_key_path(path);
+
+    let dir = install_dir(path);
+    if !dir.exists() {
+        // `ssh` insists on a private `~/.ssh`, so it is never created open.
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        builder.mode(0o700);
+        builder.create(&dir)?;
+    }
+    for checked in [dir.as_path(), path, public_path.as_path()] {
+        check_owner(checked)?;
+    }
+
+    let private_tmp = temporary_path(path);
+    let public_tmp = temporary_path(&public_path);
+    write_new(&private_tmp, &private_key, 0o600)?;
+    if let Err(e) = write_new(&public_tmp, &public_key, 0o644) {
+        let _ = fs::remove_file(&private_tmp);
+        return Err(e);
+    }
+
+    let backup = if path.exists() || public_path.exists() {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let name = format!("{}-{}", path.display(), timestamp);
+        let backup = unique_names(&name)
+            .map(PathBuf::from)
+            .find(|backup| !backup.exists() && !public_key_path(backup).exists())
+            .unwrap();
+        if path.exists() {
+            fs::rename(path, &backup)?;
+        }
+        if public_path.exists() {
+            fs::rename(&public_path, public_key_path(&backup))?;
+        }
+        Some(backup)
+    } else {
+        None
+    };
+
+    fs::rename(&private_tmp, path)?;
+    fs::rename(&public_tmp, &public_path)?;
+
+    Ok(Installed {
+        path: path.to_path_buf(),
+        backup,
+    })
+}
+
+fn install_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Appends a `Host` block to the OpenSSH client config at `config` that
+/// uses `identity_file` for `host`. Leaves the config alone if it already
+/// has a block for exactly that host.
+pub fn add_host_block(config: &Path, host: &str, identity_file: &Path) -> io::Result<bool> {
+    check_owner(config)?;
+    let existing = match fs::read_to_string(config) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let has_block = existing.lines().any(|line| {
+        let mut words = line.split_whitespace();
+        words
+            .next()
+            .is_some_and(|keyword| keyword.eq_ignore_ascii_case("host"))
+            && words.eq([host])
+    });
+    if has_block {
+        return Ok(false);
+    }
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(config)?;
+    let separator = if existing.is_empty() || existing.ends_with("\n\n") {
+        ""
+    } else if existing.ends_with('\n') {
+        "\n"
+    } else {
+        "\n\n"
+    };
+    // Quoted, so a path with spaces stays one argument.
+    write!(
+        file,
+        "{}Host {}\n    IdentityFile \"{}\"\n",
+        separator,
+        host,
+        identity_file.display()
+    )?;
+    Ok(true)
+}
+
+/// Fails if `path` exists and belongs to another user than the one running.
+fn check_owner(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        let uid = unsafe { libc::geteuid() };
+        if metadata.uid() != uid {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} belongs to user {}, not to the current user {}",
+                    path.display(),
+                    metadata.uid(),
+                    uid
+                ),
+            ));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Fails if `path` exists and its owner cannot write to it.
+fn check_writable(path: &Path) -> io::Result<()> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    #[cfg(unix)]
+    let writable = metadata.permissions().mode() & 0o200 != 0;
+    #[cfg(not(unix))]
+    let writable = !metadata.permissions().readonly();
+    if !writable {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not writable", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+fn write_new(path: &Path, contents: &[u8], mode: u32) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(mode);
+    #[cfg(not(unix))]
+    let _ = mode;
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("vanity-ssh-rs-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn host_block_quotes_the_identity_file() {
+        let dir = temp_dir("host-block");
+        let config = dir.join("config");
+        let identity = dir.join("my keys").join("id_ed25519");
+
+        assert!(add_host_block(&config, "box", &identity).unwrap());
+        assert!(!add_host_block(&config, "box", &identity).unwrap());
+        assert_eq!(
+            fs::read_to_string(&config).unwrap(),
+            format!("Host box\n    IdentityFile \"{}\"\n", identity.display())
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_target_is_rejected_before_the_search() {
+        let dir = temp_dir("install-read-only");
+        let config = dir.join("config");
+        fs::write(&config, "").unwrap();
+        let path = dir.join("id_ed25519");
+        assert!(check_install_target(&path, Some(&config)).is_ok());
+
+        fs::set_permissions(&config, fs::Permissions::from_mode(0o400)).unwrap();
+        assert!(check_install_target(&path, Some(&config)).is_err());
+        assert!(check_install_target(&path, None).is_ok());
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o500)).unwrap();
+        assert!(check_install_target(&path, None).is_err());
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn missing_directories_are_created_private() {
+        let dir = temp_dir("install-new-dir");
+        let saved = dir.join("saved");
+        fs::write(&saved, "private").unwrap();
+        fs::write(public_key_path(&saved), "public").unwrap();
+        let path = dir.join("home").join(".ssh").join("id_ed25519");
+
+        let installed = install_key_pair(&saved, &path).unwrap();
+
+        assert!(installed.backup.is_none());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "private");
+        for created in [dir.join("home"), dir.join("home").join(".ssh")] {
+            let mode = fs::metadata(created).unwrap().permissions().mode();
+            assert_eq!(mode & 0o077, 0, "{:o}", mode);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,41 +1,369 @@
+use clap::ValueEnum;
+use curve25519_dalek::constants::ED25519_BASEPOINT_POINT;
 use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
 use ed25519_dalek::SecretKey;
 use ed25519_dalek::hazmat::ExpandedSecretKey;
 use rand::RngCore;
 use rand::rngs::OsRng;
+use sha2::{Digest, Sha512};
+use ssh_key::private::PrivateKey;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroize;
+
+use crate::core::backend::{BatchKeyPair, KeyBackend};
+use crate::core::ecdsa::Curve;
+use crate::core::file_io::{
+    KeyFileOptions, create_ssh_keypair_from_ed25519_keys, remove_openssh_key, write_openssh_key,
+};
+use crate::core::fingerprint::openssh_fingerprint;
+use crate::core::layout::{BASE64_ALPHABET, Layout, OPENSSH_LAYOUT};
+use crate::core::mask::{BASE64_LEN, sextet_at};
+use crate::core::pattern::{Candidate, Pattern, create_openssh_public_key_from_keypair};
+use crate::core::pattern_set::PatternSet;
+use crate::core::secret::SecretBytes;
 
 pub const BATCH_SIZE: usize = 100;
 
-#[derive(Debug, Clone)]
-pub struct KeyPair {
-    pub secret_key: SecretKey,
-    pub public_key: CompressedEdwardsY,
+/// Secret half of an ed25519 key pair.
+#[derive(Debug)]
+pub enum Secret {
+    /// 32-byte seed the signing scalar is derived from, as stored by OpenSSH.
+    Seed(SecretBytes<32>),
+    /// Clamped signing scalar of a key found by the point walk, which has no
+    /// seed.
+    Scalar(SecretBytes<32>),
+}
+
+impl Secret {
+    /// Moves the secret into locked memory, see [`SecretBytes::lock`].
+    pub fn lock(&mut self) -> io::Result<()> {
+        match self {
+            Secret::Seed(seed) => seed.lock(),
+            Secret::Scalar(scalar) => scalar.lock(),
+        }
+    }
+
+    /// Moves the secret out, see [`SecretBytes::take`].
+    pub fn take(&mut self) -> Secret {
+        match self {
+            Secret::Seed(seed) => Secret::Seed(seed.take()),
+            Secret::Scalar(scalar) => Secret::Scalar(scalar.take()),
+        }
+    }
+
+    /// A second copy of the secret, for the rare key that has to be sent to
+    /// the manager twice. There is no `Clone` so that copies stay deliberate.
+    pub fn duplicate(&self) -> Secret {
+        match self {
+            Secret::Seed(seed) => {
+                let mut copy = SecretBytes::zeroed();
+                copy.expose_mut().copy_from_slice(seed.expose());
+                Secret::Seed(copy)
+            }
+            Secret::Scalar(scalar) => {
+                let mut copy = SecretBytes::zeroed();
+                copy.expose_mut().copy_from_slice(scalar.expose());
+                Secret::Scalar(copy)
+            }
+        }
+    }
+
+    /// The clamped scalar followed by the nonce prefix, as stored by Tor.
+    /// Both are derived from the seed if that is what the key pair has.
+    pub fn expanded(&self) -> SecretBytes<64> {
+        let mut expanded = SecretBytes::zeroed();
+        match self {
+            Secret::Seed(seed) => {
+                sha512_into(seed.expose(), expanded.expose_mut());
+                clamp_scalar_bytes(expanded.expose_mut());
+            }
+            Secret::Scalar(scalar) => {
+                // The nonce prefix is hashed from the scalar itself. Keys of
+                // one walk sharing a prefix would sign a message with the
+                // same nonce, and as their scalars differ by a known step,
+                // two such signatures would give the scalar away.
+                sha512_into(scalar.expose(), expanded.expose_mut());
+                expanded.expose_mut()[..32].copy_from_slice(scalar.expose());
+            }
+        }
+        expanded
+    }
+}
+
+/// Encoding of the public points of a batch, e.g. the compressed Edwards
+/// form ed25519 uses or the Montgomery form of X25519.
+pub trait PublicPoint: Copy + Default + fmt::Debug + Send + 'static {
+    fn encode_batch(points: &[EdwardsPoint]) -> Vec<Self>;
+}
+
+impl PublicPoint for CompressedEdwardsY {
+    fn encode_batch(points: &[EdwardsPoint]) -> Vec<Self> {
+        EdwardsPoint::compress_batch(points)
+    }
+}
+
+/// A key pair on Curve25519, with its public point encoded as `P`.
+#[derive(Debug)]
+pub struct Curve25519KeyPair<P: PublicPoint> {
+    pub secret_key: Secret,
+    pub public_key: P,
 }
 
-pub fn generate_keypair_batch(batch_size: usize) -> Vec<KeyPair> {
+pub type Ed25519KeyPair = Curve25519KeyPair<CompressedEdwardsY>;
+
+impl<P: PublicPoint> BatchKeyPair for Curve25519KeyPair<P> {
+    fn take(&mut self) -> Self {
+        Curve25519KeyPair {
+            secret_key: self.secret_key.take(),
+            public_key: self.public_key,
+        }
+    }
+
+    fn duplicate(&self) -> Self {
+        Curve25519KeyPair {
+            secret_key: self.secret_key.duplicate(),
+            public_key: self.public_key,
+        }
+    }
+
+    fn lock(&mut self) -> io::Result<()> {
+        self.secret_key.lock()
+    }
+}
+
+/// OpenSSH ed25519 keys, the first [`KeyBackend`]. Patterns match the base64
+/// key blob, read straight from the public key bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct Ed25519Backend {
+    pub strategy: Strategy,
+}
+
+impl KeyBackend for Ed25519Backend {
+    type KeyPair = Ed25519KeyPair;
+
+    fn layout(&self) -> &'static Layout {
+        &OPENSSH_LAYOUT
+    }
+
+    fn generate_batch(&self, batch_size: usize) -> Vec<Ed25519KeyPair> {
+        self.strategy.generate_batch(batch_size)
+    }
+
+    fn text(&self, keypair: &Ed25519KeyPair) -> String {
+        Candidate::new(keypair).base64().to_string()
+    }
+
+    fn variable_text(&self, keypair: &Ed25519KeyPair, text: &mut Vec<u8>) {
+        let public_key = keypair.public_key.as_bytes();
+        text.clear();
+        text.extend(
+            (OPENSSH_LAYOUT.variable_start()..BASE64_LEN)
+                .map(|i| BASE64_ALPHABET[sextet_at(public_key, i) as usize]),
+        );
+    }
+
+    fn find_match<'a>(
+        &self,
+        keypair: &Ed25519KeyPair,
+        patterns: &'a PatternSet,
+    ) -> Option<&'a Pattern> {
+        let public_key = keypair.public_key.as_bytes();
+        let candidate = Candidate::new(keypair);
+        patterns.find_match(
+            |i| {
+                (i < BASE64_LEN)
+                    .then(|| BASE64_ALPHABET[sextet_at(public_key, BASE64_LEN - 1 - i) as usize])
+            },
+            |p| candidate.matches(p),
+        )
+    }
+
+    fn matched_word(&self, keypair: &Ed25519KeyPair, pattern: &Pattern) -> Option<String> {
+        let Pattern::Dictionary(dictionary) = pattern else {
+            return None;
+        };
+        Candidate::new(keypair).word(dictionary).map(str::to_string)
+    }
+
+    fn fingerprint(&self, keypair: &Ed25519KeyPair) -> String {
+        openssh_fingerprint(&create_openssh_public_key_from_keypair(keypair))
+    }
+
+    fn write(
+        &self,
+        keypair: &Ed25519KeyPair,
+        dir: &Path,
+        name: &str,
+        options: &KeyFileOptions,
+    ) -> io::Result<PathBuf> {
+        let private_key = PrivateKey::from(create_ssh_keypair_from_ed25519_keys(keypair)?);
+        write_openssh_key(private_key, dir, name, options)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        remove_openssh_key(path)
+    }
+}
+
+/// How candidate key pairs are generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Strategy {
+    /// Random seed and a full scalar multiplication per candidate
+    #[default]
+    Seed,
+    /// Start from one random scalar and step by adding the base point. Only
+    /// for targets that store the expanded secret key.
+    PointWalk,
+}
+
+/// Type of the generated OpenSSH key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum KeyType {
+    #[default]
+    Ed25519,
+    /// ECDSA on NIST P-256, found by the point walk whatever the strategy
+    EcdsaNistp256,
+    /// ECDSA on NIST P-384, found by the point walk whatever the strategy
+    EcdsaNistp384,
+    /// ECDSA on NIST P-521, found by the point walk whatever the strategy
+    EcdsaNistp521,
+    /// RSA with one modulus per run, found by varying the public exponent
+    Rsa,
+}
+
+impl KeyType {
+    /// The file `ssh-keygen` writes keys of this type to.
+    pub fn default_file_name(&self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => "id_ed25519",
+            KeyType::Rsa => "id_rsa",
+            _ => "id_ecdsa",
+        }
+    }
+
+    pub fn curve(&self) -> Option<Curve> {
+        match self {
+            KeyType::Ed25519 | KeyType::Rsa => None,
+            KeyType::EcdsaNistp256 => Some(Curve::NistP256),
+            KeyType::EcdsaNistp384 => Some(Curve::NistP384),
+            KeyType::EcdsaNistp521 => Some(Curve::NistP521),
+        }
+    }
+}
+
+impl Strategy {
+    pub fn generate_batch<P: PublicPoint>(&self, batch_size: usize) -> Vec<Curve25519KeyPair<P>> {
+        match self {
+            Strategy::Seed => seed_batch(batch_size),
+            Strategy::PointWalk => point_walk_batch(batch_size),
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub fn generate_keypair_batch(batch_size: usize) -> Vec<Ed25519KeyPair> {
+    seed_batch(batch_size)
+}
+
+#[allow(dead_code)]
+pub fn generate_keypair_batch_point_walk(batch_size: usize) -> Vec<Ed25519KeyPair> {
+    point_walk_batch(batch_size)
+}
+
+/// The secrets are generated in place in the returned `Vec`, so dropping
+/// the batch wipes every one of them.
+fn seed_batch<P: PublicPoint>(batch_size: usize) -> Vec<Curve25519KeyPair<P>> {
     let mut csprng = OsRng;
-    let mut secret_keys = Vec::with_capacity(batch_size);
+    let mut keypairs = Vec::with_capacity(batch_size);
     let mut public_points = Vec::with_capacity(batch_size);
 
     for _ in 0..batch_size {
-        let mut secret_key = SecretKey::default();
-        csprng.fill_bytes(&mut secret_key);
-        let expanded_secret_key = ExpandedSecretKey::from(&secret_key);
-        let public_point = EdwardsPoint::mul_base(&expanded_secret_key.scalar);
-        secret_keys.push(secret_key);
-        public_points.push(public_point);
-    }
-
-    let compressed_points = EdwardsPoint::compress_batch(&public_points);
-
-    secret_keys
-        .into_iter()
-        .zip(compressed_points.into_iter())
-        .map(|(secret_key, compressed)| KeyPair {
-            secret_key,
-            public_key: compressed,
-        })
-        .collect()
+        let mut seed = SecretBytes::zeroed();
+        csprng.fill_bytes(seed.expose_mut());
+        let expanded_secret_key = ExpandedSecretKey::from(seed.expose());
+        public_points.push(EdwardsPoint::mul_base(&expanded_secret_key.scalar));
+        keypairs.push(Curve25519KeyPair {
+            secret_key: Secret::Seed(seed),
+            public_key: P::default(),
+        });
+    }
+
+    for (keypair, encoded) in keypairs.iter_mut().zip(P::encode_batch(&public_points)) {
+        keypair.public_key = encoded;
+    }
+    keypairs
+}
+
+/// Generates `batch_size` consecutive keys `a + 8i` from one random clamped
+/// scalar `a`, so each candidate costs a point addition instead of a full
+/// scalar multiplication. Stepping by 8 keeps the low three bits clear and
+/// the scalar stays clamped. Only the scalar is kept per key, its nonce
+/// prefix is derived when [`Secret::expanded`] is called, so the saved keys
+/// look independently generated.
+fn point_walk_batch<P: PublicPoint>(batch_size: usize) -> Vec<Curve25519KeyPair<P>> {
+    let mut csprng = OsRng;
+    let mut seed = SecretBytes::<32>::zeroed();
+    csprng.fill_bytes(seed.expose_mut());
+    let mut expanded = SecretBytes::<64>::zeroed();
+    sha512_into(seed.expose(), expanded.expose_mut());
+    clamp_scalar_bytes(expanded.expose_mut());
+    let mut scalar = SecretBytes::<32>::zeroed();
+    scalar
+        .expose_mut()
+        .copy_from_slice(&expanded.expose()[..32]);
+
+    let step = ED25519_BASEPOINT_POINT.mul_by_cofactor();
+    let mut point = EdwardsPoint::mul_base(&Scalar::from_bytes_mod_order(*scalar.expose()));
+
+    let mut keypairs = Vec::with_capacity(batch_size);
+    let mut public_points = Vec::with_capacity(batch_size);
+    for _ in 0..batch_size {
+        let mut secret = SecretBytes::zeroed();
+        secret.expose_mut().copy_from_slice(scalar.expose());
+        keypairs.push(Curve25519KeyPair {
+            secret_key: Secret::Scalar(secret),
+            public_key: P::default(),
+        });
+        public_points.push(point);
+        point += step;
+        add_to_scalar_bytes(scalar.expose_mut(), 8);
+    }
+
+    for (keypair, encoded) in keypairs.iter_mut().zip(P::encode_batch(&public_points)) {
+        keypair.public_key = encoded;
+    }
+    keypairs
+}
+
+/// Writes SHA-512 of `input` to `output` without leaving a copy behind.
+pub fn sha512_into(input: &[u8], output: &mut [u8; 64]) {
+    let mut digest = Sha512::digest(input);
+    output.copy_from_slice(&digest);
+    digest.zeroize();
+}
+
+pub fn clamp_scalar_bytes(expanded: &mut [u8; 64]) {
+    expanded[0] &= 248;
+    expanded[31] &= 127;
+    expanded[31] |= 64;
+}
+
+/// Adds `value` to the little-endian scalar. A clamped
+/// scalar would need 2^251 steps before the addition reaches bit 254, so the
+/// carry never leaves the scalar.
+fn add_to_scalar_bytes(scalar: &mut [u8; 32], value: u8) {
+    let mut carry = value as u16;
+    for byte in scalar {
+        if carry == 0 {
+            break;
+        }
+        let sum = *byte as u16 + carry;
+        *byte = sum as u8;
+        carry = sum >> 8;
+    }
 }
 
 pub mod bench_helpers {
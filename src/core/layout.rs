@@ -0,0 +1,400 @@
+use rand::Rng;
+use std::sync::LazyLock;
+
+use crate::core::mask::BLOB_HEADER;
+
+pub const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Layout of the base64 key blob in an OpenSSH ed25519 public key: the fixed
+/// header followed by 32 uniformly random key bytes.
+pub static OPENSSH_LAYOUT: LazyLock<Layout> = LazyLock::new(|| {
+    let blob: Vec<BlobByte> = BLOB_HEADER
+        .iter()
+        .map(|&b| BlobByte::Fixed(b))
+        .chain(std::iter::repeat_n(BlobByte::Random, 32))
+        .collect();
+    Layout::encoded("public key", &blob, BASE64_ALPHABET)
+});
+
+/// A byte of an encoded blob, either fixed by the format or uniformly random.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobByte {
+    Fixed(u8),
+    Random,
+    /// The bits set in `mask` are those of `value`, the others are random.
+    Masked {
+        mask: u8,
+        value: u8,
+    },
+}
+
+/// Set of ASCII characters, stored as a bit mask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CharSet(u128);
+
+impl CharSet {
+    pub fn insert(&mut self, c: u8) {
+        if c.is_ascii() {
+            self.0 |= 1 << c;
+        }
+    }
+
+    pub fn contains(&self, c: u8) -> bool {
+        c.is_ascii() && self.0 & (1 << c) != 0
+    }
+
+    pub fn len(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..128u8).filter(|&c| self.contains(c))
+    }
+
+    pub fn union(&self, other: &CharSet) -> CharSet {
+        CharSet(self.0 | other.0)
+    }
+
+    pub fn intersection(&self, other: &CharSet) -> CharSet {
+        CharSet(self.0 & other.0)
+    }
+
+    /// Compact description of the set with ranges collapsed, e.g. `A-P` or
+    /// `+/0-9A-Za-z`.
+    pub fn describe(&self) -> String {
+        let chars: Vec<u8> = self.iter().collect();
+        let mut description = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let mut end = i;
+            // Only runs of letters or digits are collapsed, so a range never
+            // reads as if it contained punctuation.
+            while end + 1 < chars.len()
+                && chars[end + 1] == chars[end] + 1
+                && chars[end + 1].is_ascii_alphanumeric()
+                && chars[i].is_ascii_alphanumeric()
+            {
+                end += 1;
+            }
+            description.push(chars[i] as char);
+            if end - i >= 2 {
+                description.push('-');
+                description.push(chars[end] as char);
+            } else if end > i {
+                description.push(chars[end] as char);
+            }
+            i = end + 1;
+        }
+        description
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Distribution of the text a pattern is matched against: for every position
+/// the set of characters that can occur there, each equally likely.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Layout {
+    /// What the text is, for messages.
+    pub name: &'static str,
+    positions: Vec<CharSet>,
+}
+
+impl Layout {
+    /// Layout of `blob` encoded with a power-of-two `alphabet` (base64 or
+    /// base32), most significant bit first and without padding characters.
+    pub fn encoded(name: &'static str, blob: &[BlobByte], alphabet: &[u8]) -> Self {
+        let bits_per_char = alphabet.len().trailing_zeros() as usize;
+        let total_bits = blob.len() * 8;
+        let n_chars = total_bits.div_ceil(bits_per_char);
+
+        let positions = (0..n_chars)
+            .map(|i| {
+                let mut fixed_mask = 0usize;
+                let mut fixed_value = 0usize;
+                for b in 0..bits_per_char {
+                    let bit = i * bits_per_char + b;
+                    let shift = bits_per_char - 1 - b;
+                    // Bits past the end of the blob are zero-filled.
+                    let fixed_bit = match blob.get(bit / 8) {
+                        None => Some(0),
+                        Some(BlobByte::Fixed(byte)) => Some((*byte as usize >> (7 - bit % 8)) & 1),
+                        Some(BlobByte::Random) => None,
+                        Some(BlobByte::Masked { mask, value }) => {
+                            let in_byte = 7 - bit % 8;
+                            (mask >> in_byte & 1 == 1).then_some((*value as usize >> in_byte) & 1)
+                        }
+                    };
+                    if let Some(value) = fixed_bit {
+                        fixed_mask |= 1 << shift;
+                        fixed_value |= value << shift;
+                    }
+                }
+
+                let mut set = CharSet::default();
+                for (value, &c) in alphabet.iter().enumerate() {
+                    if value & fixed_mask == fixed_value {
+                        set.insert(c);
+                    }
+                }
+                set
+            })
+            .collect();
+
+        Self { name, positions }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn position(&self, index: usize) -> CharSet {
+        self.positions[index]
+    }
+
+    /// All characters that occur at any position.
+    pub fn alphabet(&self) -> CharSet {
+        self.positions
+            .iter()
+            .fold(CharSet::default(), |all, set| all.union(set))
+    }
+
+    /// The characters every text starts with because the format fixes them.
+    pub fn fixed_prefix(&self) -> String {
+        self.positions[..self.variable_start()]
+            .iter()
+            .filter_map(|set| set.iter().next())
+            .map(char::from)
+            .collect()
+    }
+
+    /// Index of the first character that is not completely fixed by the
+    /// format, where prefix patterns start.
+    pub fn variable_start(&self) -> usize {
+        self.positions
+            .iter()
+            .position(|set| set.len() > 1)
+            .unwrap_or(self.len())
+    }
+
+    /// Draws a random text distributed as this layout.
+    pub fn sample(&self, rng: &mut impl Rng) -> String {
+        self.positions
+            .iter()
+            .map(|set| {
+                let index = rng.gen_range(0..set.len() as usize);
+                set.iter().nth(index).map(char::from).unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// Probability that `text` appears at `offset`.
+    pub fn literal_probability(&self, offset: usize, text: &str) -> f64 {
+        if offset + text.len() > self.len() {
+            return 0.0;
+        }
+        text.bytes()
+            .zip(&self.positions[offset..])
+            .map(|(c, set)| {
+                if set.contains(c) {
+                    1.0 / set.len() as f64
+                } else {
+                    0.0
+                }
+            })
+            .product()
+    }
+
+    /// Probability that the text ends with characters from `classes`, one
+    /// class per position.
+    pub fn class_suffix_probability(&self, classes: &[CharSet]) -> f64 {
+        let Some(offset) = self.len().checked_sub(classes.len()) else {
+            return 0.0;
+        };
+        classes
+            .iter()
+            .zip(&self.positions[offset..])
+            .map(|(class, set)| set.intersection(class).len() as f64 / set.len() as f64)
+            .product()
+    }
+
+    /// Probability that the text ends with `suffix`.
+    pub fn suffix_probability(&self, suffix: &str) -> f64 {
+        match self.len().checked_sub(suffix.len()) {
+            Some(offset) => self.literal_probability(offset, suffix),
+            None => 0.0,
+        }
+    }
+
+    /// Probability that `text` occurs anywhere. Runs the KMP automaton for
+    /// `text` over the per-position character distributions, so overlapping
+    /// occurrences and the fixed parts of the layout are accounted for
+    /// exactly.
+    pub fn contains_probability(&self, text: &str) -> f64 {
+        let needle = text.as_bytes();
+        if needle.is_empty() {
+            return 1.0;
+        }
+
+        let failure = kmp_failure(needle);
+        let next_state = |mut state: usize, c: u8| loop {
+            if needle[state] == c {
+                return state + 1;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = failure[state - 1];
+        };
+
+        // Probability of having matched `state` characters of the needle
+        // without having seen a full occurrence yet.
+        let mut distribution = vec![0.0; needle.len()];
+        distribution[0] = 1.0;
+        let mut matched = 0.0;
+
+        for set in &self.positions {
+            let weight = 1.0 / set.len() as f64;
+            let mut next = vec![0.0; needle.len()];
+            for (state, &p) in distribution.iter().enumerate() {
+                if p == 0.0 {
+                    continue;
+                }
+                for c in set.iter() {
+                    match next_state(state, c) {
+                        n if n == needle.len() => matched += p * weight,
+                        n => next[n] += p * weight,
+                    }
+                }
+            }
+            distribution = next;
+        }
+
+        matched
+    }
+}
+
+fn kmp_failure(needle: &[u8]) -> Vec<usize> {
+    let mut failure = vec![0; needle.len()];
+    let mut k = 0;
+    for i in 1..needle.len() {
+        while k > 0 && needle[i] != needle[k] {
+            k = failure[k - 1];
+        }
+        if needle[i] == needle[k] {
+            k += 1;
+        }
+        failure[i] = k;
+    }
+    failure
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 12 characters from `abcd`: 6 random ones followed by the fixed
+    /// `bcabcd`, so every text can be enumerated and needles can run into the
+    /// fixed part.
+    fn small_layout() -> Layout {
+        let blob = [
+            BlobByte::Random,
+            BlobByte::Masked {
+                mask: 0x0f,
+                value: 0x06,
+            },
+            BlobByte::Fixed(0x1b),
+        ];
+        Layout::encoded("test", &blob[..], b"abcd")
+    }
+
+    /// Every text of the layout, all equally likely.
+    fn all_texts(layout: &Layout) -> Vec<String> {
+        (0..layout.len()).fold(vec![String::new()], |texts, i| {
+            let chars: Vec<u8> = layout.position(i).iter().collect();
+            texts
+                .iter()
+                .flat_map(|text| chars.iter().map(move |&c| format!("{}{}", text, c as char)))
+                .collect()
+        })
+    }
+
+    fn share(texts: &[String], matches: impl Fn(&str) -> bool) -> f64 {
+        texts.iter().filter(|text| matches(text)).count() as f64 / texts.len() as f64
+    }
+
+    #[test]
+    fn small_layout_has_fixed_positions() {
+        let layout = small_layout();
+        assert_eq!(layout.len(), 12);
+        assert_eq!(all_texts(&layout).len(), 1 << 12);
+        assert_eq!(layout.fixed_prefix(), "");
+        assert_eq!(layout.position(5).len(), 4);
+        assert!((6..12).all(|i| layout.position(i).len() == 1));
+    }
+
+    /// Overlapping needles are where counting occurrences independently goes
+    /// wrong, so the automaton is checked against a count of every text.
+    #[test]
+    fn contains_probability_counts_overlapping_occurrences() {
+        let layout = small_layout();
+        let texts = all_texts(&layout);
+        for needle in [
+            "a", "aa", "aaa", "abab", "aba", "abca", "bcb", "dbd", "cbd", "ddd",
+        ] {
+            let expected = share(&texts, |text| text.contains(needle));
+            let actual = layout.contains_probability(needle);
+            assert!(
+                (actual - expected).abs() < 1e-12,
+                "{}: {} != {}",
+                needle,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn literal_probability_counts_texts_at_the_offset() {
+        let layout = small_layout();
+        let texts = all_texts(&layout);
+        for (offset, text) in [
+            (0, "ab"),
+            (4, "cb"),
+            (5, "ab"),
+            (6, "a"),
+            (9, "bcd"),
+            (10, "abc"),
+        ] {
+            let expected = share(&texts, |t| t[offset..].starts_with(text));
+            assert_eq!(
+                layout.literal_probability(offset, text),
+                expected,
+                "{} at {}",
+                text,
+                offset
+            );
+        }
+        assert_eq!(
+            layout.suffix_probability("bd"),
+            share(&texts, |t| t.ends_with("bd"))
+        );
+    }
+
+    #[test]
+    fn openssh_probabilities() {
+        assert_eq!(OPENSSH_LAYOUT.suffix_probability("ab"), 1.0 / 4096.0);
+        // Character 25 holds the last 2 bits of the header and 4 key bits.
+        assert_eq!(OPENSSH_LAYOUT.literal_probability(25, "A"), 1.0 / 16.0);
+        assert_eq!(OPENSSH_LAYOUT.literal_probability(25, "a"), 0.0);
+        assert_eq!(OPENSSH_LAYOUT.literal_probability(0, "AAAAC3"), 1.0);
+    }
+}
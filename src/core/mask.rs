@@ -0,0 +1,337 @@
+use crate::core::layout::CharSet;
+
+/// Fixed header of the ed25519 OpenSSH public key blob: the length-prefixed
+/// key type name followed by the length prefix of the 32-byte public key.
+pub const BLOB_HEADER: [u8; 19] = *b"\0\0\0\x0bssh-ed25519\0\0\0\x20";
+/// Length of the full key blob (header plus 32 key bytes).
+pub const BLOB_LEN: usize = BLOB_HEADER.len() + 32;
+/// Length of the base64 encoding of the key blob. 51 bytes encode to exactly
+/// 68 characters, so there is never any padding.
+pub const BASE64_LEN: usize = BLOB_LEN / 3 * 4;
+
+const KEY_WORDS: usize = 4;
+
+/// Bit mask over the 32 public key bytes, compiled from base64 characters at
+/// fixed positions of the 68-character key blob encoding.
+///
+/// Matching a candidate key is a masked compare of at most four `u64` words,
+/// without ever rendering the key as a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyMask {
+    mask: [u64; KEY_WORDS],
+    value: [u64; KEY_WORDS],
+    first_word: usize,
+    satisfiable: bool,
+}
+
+impl KeyMask {
+    /// Mask for a string the base64 key blob must end with.
+    pub fn suffix(suffix: &str) -> Self {
+        match BASE64_LEN.checked_sub(suffix.len()) {
+            Some(position) => Self::at(position, suffix),
+            None => Self::unsatisfiable(),
+        }
+    }
+
+    /// Mask for a string that must appear at `position` in the base64 key blob.
+    ///
+    /// Characters that fall onto the fixed blob header are checked once here;
+    /// if they disagree with the header the mask can never match.
+    pub fn at(position: usize, chars: &str) -> Self {
+        let mut mask = [0u8; 32];
+        let mut value = [0u8; 32];
+
+        for (i, c) in chars.bytes().enumerate() {
+            let pos = position + i;
+            let Some(sextet) = base64_value(c) else {
+                return Self::unsatisfiable();
+            };
+            if pos >= BASE64_LEN {
+                return Self::unsatisfiable();
+            }
+
+            for bit in 0..6 {
+                let blob_bit = pos * 6 + bit;
+                let expected = (sextet >> (5 - bit)) & 1;
+                let byte = blob_bit / 8;
+                let shift = 7 - blob_bit % 8;
+
+                if byte < BLOB_HEADER.len() {
+                    if (BLOB_HEADER[byte] >> shift) & 1 != expected {
+                        return Self::unsatisfiable();
+                    }
+                } else {
+                    let key_byte = byte - BLOB_HEADER.len();
+                    mask[key_byte] |= 1 << shift;
+                    value[key_byte] |= expected << shift;
+                }
+            }
+        }
+
+        Self::from_bytes(mask, value)
+    }
+
+    /// Mask for hex digits at nibble `offset` of the 64-digit hex encoding
+    /// of the public key, where `.` accepts any digit.
+    pub fn hex(offset: usize, digits: &str) -> Self {
+        let mut mask = [0u8; 32];
+        let mut value = [0u8; 32];
+
+        for (i, c) in digits.chars().enumerate() {
+            let nibble = offset + i;
+            let Some(digit) = c.to_digit(16) else {
+                continue;
+            };
+            if nibble >= 64 {
+                return Self::unsatisfiable();
+            }
+            // The high nibble of each byte comes first.
+            let shift = (1 - nibble % 2) * 4;
+            mask[nibble / 2] |= 0xf << shift;
+            value[nibble / 2] |= (digit as u8) << shift;
+        }
+
+        Self::from_bytes(mask, value)
+    }
+
+    fn from_bytes(mask: [u8; 32], value: [u8; 32]) -> Self {
+        let mask = to_words(&mask);
+        let value = to_words(&value);
+        let first_word = mask.iter().position(|&w| w != 0).unwrap_or(KEY_WORDS);
+
+        Self {
+            mask,
+            value,
+            first_word,
+            satisfiable: true,
+        }
+    }
+
+    /// Number of key bits the mask fixes.
+    pub fn fixed_bits(&self) -> u32 {
+        self.mask.iter().map(|w| w.count_ones()).sum()
+    }
+
+    /// Mask matching the keys both masks match, or `None` if they fix some
+    /// bit to different values.
+    pub fn combine(&self, other: &Self) -> Option<Self> {
+        if !self.satisfiable || !other.satisfiable {
+            return None;
+        }
+        let mut mask = [0; KEY_WORDS];
+        let mut value = [0; KEY_WORDS];
+        for w in 0..KEY_WORDS {
+            let common = self.mask[w] & other.mask[w];
+            if (self.value[w] ^ other.value[w]) & common != 0 {
+                return None;
+            }
+            mask[w] = self.mask[w] | other.mask[w];
+            value[w] = self.value[w] | other.value[w];
+        }
+        Some(Self {
+            mask,
+            value,
+            first_word: mask.iter().position(|&w| w != 0).unwrap_or(KEY_WORDS),
+            satisfiable: true,
+        })
+    }
+
+    fn unsatisfiable() -> Self {
+        Self {
+            mask: [0; KEY_WORDS],
+            value: [0; KEY_WORDS],
+            first_word: KEY_WORDS,
+            satisfiable: false,
+        }
+    }
+
+    #[inline]
+    pub fn matches(&self, public_key: &[u8; 32]) -> bool {
+        self.satisfiable
+            && (self.first_word..KEY_WORDS).rev().all(|w| {
+                let word = u64::from_be_bytes(public_key[w * 8..w * 8 + 8].try_into().unwrap());
+                word & self.mask[w] == self.value[w]
+            })
+    }
+}
+
+/// Allowed base64 values for characters at fixed positions of the key blob
+/// encoding, for patterns where a position accepts several characters.
+///
+/// Each character is read straight from the key bytes, so matching does not
+/// render the key as a string either.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassMask {
+    positions: Vec<(usize, u64)>,
+    satisfiable: bool,
+}
+
+impl ClassMask {
+    /// Mask for a suffix whose characters may each be any of `classes`.
+    pub fn suffix(classes: &[CharSet]) -> Self {
+        let Some(start) = BASE64_LEN.checked_sub(classes.len()) else {
+            return Self {
+                positions: Vec::new(),
+                satisfiable: false,
+            };
+        };
+
+        let positions = classes
+            .iter()
+            .enumerate()
+            .map(|(i, class)| {
+                let allowed = class
+                    .iter()
+                    .filter_map(base64_value)
+                    .fold(0u64, |allowed, value| allowed | 1 << value);
+                (start + i, allowed)
+            })
+            .collect();
+
+        Self {
+            positions,
+            satisfiable: true,
+        }
+    }
+
+    #[inline]
+    pub fn matches(&self, public_key: &[u8; 32]) -> bool {
+        self.satisfiable
+            && self
+                .positions
+                .iter()
+                .rev()
+                .all(|&(position, allowed)| allowed >> sextet_at(public_key, position) & 1 != 0)
+    }
+}
+
+/// Base64 value of the character at `position` of the key blob encoding.
+#[inline]
+pub fn sextet_at(public_key: &[u8; 32], position: usize) -> u8 {
+    let blob_byte = |i: usize| match i {
+        i if i < BLOB_HEADER.len() => BLOB_HEADER[i],
+        i if i < BLOB_LEN => public_key[i - BLOB_HEADER.len()],
+        _ => 0,
+    };
+    let bit = position * 6;
+    let pair = (blob_byte(bit / 8) as u16) << 8 | blob_byte(bit / 8 + 1) as u16;
+    ((pair >> (10 - bit % 8)) & 0x3f) as u8
+}
+
+fn to_words(bytes: &[u8; 32]) -> [u64; KEY_WORDS] {
+    std::array::from_fn(|w| u64::from_be_bytes(bytes[w * 8..w * 8 + 8].try_into().unwrap()))
+}
+
+pub fn base64_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::keypair::generate_keypair_batch;
+    use crate::core::layout::{BASE64_ALPHABET, OPENSSH_LAYOUT};
+    use crate::core::pattern::{Candidate, Pattern};
+
+    /// `text` with the character at `i` replaced by the one whose base64
+    /// value differs in `bit`, so a mask reading the wrong bit shows up.
+    fn flip(text: &str, i: usize, bit: u8) -> String {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes[i] = BASE64_ALPHABET[(base64_value(bytes[i]).unwrap() ^ bit) as usize];
+        String::from_utf8(bytes).unwrap()
+    }
+
+    /// The text itself and every variant with one bit of one character
+    /// flipped, the highest and the lowest bit of a sextet being where an
+    /// off-by-one lands.
+    fn variants(text: &str) -> Vec<String> {
+        let mut variants = vec![text.to_string()];
+        for i in 0..text.len() {
+            variants.extend([flip(text, i, 0x20), flip(text, i, 0x01)]);
+        }
+        variants
+    }
+
+    fn assert_agrees(candidate: &Candidate, pattern: &Pattern, expected: bool) {
+        let text = candidate.base64();
+        assert_eq!(
+            pattern.matches_str(text, &OPENSSH_LAYOUT),
+            expected,
+            "{} in {}",
+            pattern,
+            text
+        );
+        assert_eq!(
+            candidate.matches(pattern),
+            expected,
+            "{} in {}",
+            pattern,
+            text
+        );
+    }
+
+    #[test]
+    fn sextets_match_the_rendered_text() {
+        for keypair in generate_keypair_batch(20) {
+            let candidate = Candidate::new(&keypair);
+            let text = candidate.base64().as_bytes().to_vec();
+            assert_eq!(text.len(), BASE64_LEN);
+            for (i, &c) in text.iter().enumerate() {
+                let sextet = sextet_at(keypair.public_key.as_bytes(), i);
+                assert_eq!(BASE64_ALPHABET[sextet as usize], c, "{}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn suffix_masks_agree_with_the_text() {
+        for keypair in generate_keypair_batch(50) {
+            let candidate = Candidate::new(&keypair);
+            let text = candidate.base64().to_string();
+            for len in 1..=10 {
+                let own = &text[BASE64_LEN - len..];
+                for suffix in variants(own) {
+                    let pattern = Pattern::Suffix(suffix.clone(), KeyMask::suffix(&suffix));
+                    assert_agrees(&candidate, &pattern, suffix == own);
+                }
+            }
+        }
+    }
+
+    /// Every offset of the text, including the characters that straddle the
+    /// fixed header and the key and those that start or end a 3-byte group.
+    #[test]
+    fn at_masks_agree_with_the_text() {
+        for keypair in generate_keypair_batch(20) {
+            let candidate = Candidate::new(&keypair);
+            let text = candidate.base64().to_string();
+            for offset in 0..BASE64_LEN {
+                for len in 1..=4.min(BASE64_LEN - offset) {
+                    let own = &text[offset..offset + len];
+                    for chars in variants(own) {
+                        let pattern =
+                            Pattern::At(offset, chars.clone(), KeyMask::at(offset, &chars));
+                        assert_agrees(&candidate, &pattern, chars == own);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn masks_past_the_end_never_match() {
+        let keypair = generate_keypair_batch(1).remove(0);
+        let key = keypair.public_key.as_bytes();
+        assert!(!KeyMask::suffix(&"A".repeat(BASE64_LEN + 1)).matches(key));
+        assert!(!KeyMask::at(BASE64_LEN - 1, "AA").matches(key));
+        assert!(!KeyMask::at(BASE64_LEN, "A").matches(key));
+        assert!(!KeyMask::suffix("a-b").matches(key));
+    }
+}
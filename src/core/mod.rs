@@ -1,3 +1,20 @@
+pub mod backend;
+pub mod dictionary;
+pub mod ecdsa;
+pub mod expression;
 pub mod file_io;
+pub mod fingerprint;
+pub mod install;
 pub mod keypair;
+pub mod layout;
+pub mod mask;
+pub mod onion;
 pub mod pattern;
+pub mod pattern_set;
+pub mod regex_layout;
+pub mod rsa;
+pub mod score;
+pub mod secret;
+pub mod target;
+pub mod validate;
+pub mod wireguard;
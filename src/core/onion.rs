@@ -0,0 +1,153 @@
+use sha3::{Digest, Sha3_256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use zeroize::Zeroizing;
+
+use crate::core::backend::KeyBackend;
+use crate::core::file_io::{KeyFileOptions, create_unique_dir, write_secret_file};
+use crate::core::keypair::{Ed25519KeyPair, Strategy};
+use crate::core::layout::{BlobByte, Layout};
+use crate::core::pattern::Pattern;
+use crate::core::pattern_set::PatternSet;
+
+pub const ONION_VERSION: u8 = 0x03;
+const CHECKSUM_PREFIX: &[u8] = b".onion checksum";
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Layout of a v3 onion address: 32 random key bytes, two checksum bytes
+/// (uniformly distributed for our purposes) and the fixed version byte.
+pub static ONION_LAYOUT: LazyLock<Layout> = LazyLock::new(|| {
+    let blob: Vec<BlobByte> = std::iter::repeat_n(BlobByte::Random, 34)
+        .chain(std::iter::once(BlobByte::Fixed(ONION_VERSION)))
+        .collect();
+    Layout::encoded("onion address", &blob, BASE32_ALPHABET)
+});
+
+/// Encodes an ed25519 public key as a Tor v3 onion address, without the
+/// `.onion` suffix:
+/// `base32(PUBKEY | CHECKSUM | VERSION)` where
+/// `CHECKSUM = SHA3-256(".onion checksum" | PUBKEY | VERSION)[..2]`.
+pub fn onion_address(public_key: &[u8; 32]) -> String {
+    let checksum = Sha3_256::new()
+        .chain_update(CHECKSUM_PREFIX)
+        .chain_update(public_key)
+        .chain_update([ONION_VERSION])
+        .finalize();
+
+    let mut blob = [0u8; 35];
+    blob[..32].copy_from_slice(public_key);
+    blob[32..34].copy_from_slice(&checksum[..2]);
+    blob[34] = ONION_VERSION;
+
+    base32_encode(&blob)
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    encoded
+}
+
+/// Tor v3 onion services. Patterns match the onion address, and the key is
+/// written as a hidden service directory.
+#[derive(Debug, Clone, Copy)]
+pub struct OnionBackend {
+    pub strategy: Strategy,
+}
+
+impl KeyBackend for OnionBackend {
+    type KeyPair = Ed25519KeyPair;
+
+    fn layout(&self) -> &'static Layout {
+        &ONION_LAYOUT
+    }
+
+    fn generate_batch(&self, batch_size: usize) -> Vec<Ed25519KeyPair> {
+        self.strategy.generate_batch(batch_size)
+    }
+
+    fn text(&self, keypair: &Ed25519KeyPair) -> String {
+        onion_address(keypair.public_key.as_bytes())
+    }
+
+    fn find_match<'a>(
+        &self,
+        keypair: &Ed25519KeyPair,
+        patterns: &'a PatternSet,
+    ) -> Option<&'a Pattern> {
+        let public_key = keypair.public_key.as_bytes();
+        let address = onion_address(public_key);
+        patterns.find_match(
+            |i| address.as_bytes().iter().rev().nth(i).copied(),
+            |p| p.matches_key(public_key, &address, &ONION_LAYOUT),
+        )
+    }
+
+    fn fingerprint(&self, keypair: &Ed25519KeyPair) -> String {
+        onion_address(keypair.public_key.as_bytes())
+    }
+
+    fn write(
+        &self,
+        keypair: &Ed25519KeyPair,
+        dir: &Path,
+        name: &str,
+        _options: &KeyFileOptions,
+    ) -> io::Result<PathBuf> {
+        save_onion_service(keypair, dir, name)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+
+    fn saved_message(&self, keypair: &Ed25519KeyPair, path: &Path) -> String {
+        format!(
+            "Onion service {}.onion saved to '{}/'",
+            self.text(keypair),
+            path.display()
+        )
+    }
+}
+
+const TOR_SECRET_KEY_HEADER: &[u8; 32] = b"== ed25519v1-secret: type0 ==\0\0\0";
+const TOR_PUBLIC_KEY_HEADER: &[u8; 32] = b"== ed25519v1-public: type0 ==\0\0\0";
+
+/// Writes a Tor v3 hidden service directory `dir/{name}` containing
+/// `hs_ed25519_secret_key`, `hs_ed25519_public_key` and `hostname`, under the
+/// first of the unique names for `name` that is still free.
+fn save_onion_service(keypair: &Ed25519KeyPair, dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let service_dir = create_unique_dir(dir, name)?;
+
+    let mut secret_file = Zeroizing::new(Vec::with_capacity(96));
+    secret_file.extend_from_slice(TOR_SECRET_KEY_HEADER);
+    // Tor stores the expanded key, the clamped scalar followed by the
+    // nonce prefix.
+    secret_file.extend_from_slice(keypair.secret_key.expanded().expose());
+    write_secret_file(&service_dir.join("hs_ed25519_secret_key"), &secret_file)?;
+
+    let mut public_file = TOR_PUBLIC_KEY_HEADER.to_vec();
+    public_file.extend_from_slice(keypair.public_key.as_bytes());
+    fs::write(service_dir.join("hs_ed25519_public_key"), public_file)?;
+
+    let address = format!("{}.onion", onion_address(keypair.public_key.as_bytes()));
+    fs::write(service_dir.join("hostname"), format!("{}\n", address))?;
+
+    Ok(service_dir)
+}
@@ -1,22 +1,159 @@
-use crate::core::keypair::KeyPair;
+use crate::core::dictionary::{Dictionary, WordPosition};
+use crate::core::expression::{and_probability, or_probability, parse_expression};
+use crate::core::fingerprint::{FINGERPRINT_LAYOUT, openssh_fingerprint};
+use crate::core::keypair::Ed25519KeyPair;
+use crate::core::layout::{BASE64_ALPHABET, CharSet, Layout, OPENSSH_LAYOUT};
+use crate::core::mask::{BASE64_LEN, ClassMask, KeyMask, sextet_at};
+use crate::core::regex_layout::{RegexProbability, regex_probability};
+use crate::core::validate::unmatchable_reason;
 use regex::Regex;
 use ssh_key::public::Ed25519PublicKey;
+use std::cell::OnceCell;
 use std::fmt::Display;
 use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::Arc;
 use std::time::Duration;
-use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Clone)]
 pub enum Pattern {
-    Suffix(String),
+    Suffix(String, KeyMask),
+    /// Suffix whose characters also match their other case, and with
+    /// [`Folding::LookAlike`] characters that look similar.
+    FoldedSuffix(String, Folding, ClassMask),
+    /// Text right after the part of the encoding that is fixed by the format,
+    /// e.g. after `AAAAC3NzaC1lZDI1NTE5AAAAI` for OpenSSH ed25519 keys.
+    Prefix(String, KeyMask),
+    /// Text at an absolute character offset of the encoding.
+    At(usize, String, KeyMask),
+    Contains(String),
+    /// Hex digits at the end of the 64-digit hex encoding of the public key
+    /// bytes, or at its start if they begin with `^`. `.` accepts any digit.
+    Hex(String, KeyMask),
     Regex(Regex),
+    /// Any word of a dictionary at one end of the text.
+    Dictionary(Arc<Dictionary>),
+    /// Applies the inner pattern to the SHA256 fingerprint instead of the
+    /// public key, i.e. the part after `SHA256:` in `ssh-keygen -l`.
+    Fingerprint(Box<Pattern>),
+    /// Matches if all of the patterns match.
+    And(Vec<Pattern>),
+    /// Matches if any of the patterns matches.
+    Or(Vec<Pattern>),
+    /// Matches if the pattern does not.
+    Not(Box<Pattern>),
+}
+
+const FINGERPRINT_PREFIX: &str = "fp:";
+const SUFFIX_PREFIX: &str = "suffix:";
+const PREFIX_PREFIX: &str = "prefix:";
+const CONTAINS_PREFIX: &str = "contains:";
+const AT_PREFIX: &str = "at:";
+const CASE_INSENSITIVE_PREFIX: &str = "ci:";
+const LOOK_ALIKE_PREFIX: &str = "lookalike:";
+const REGEX_PREFIX: &str = "re:";
+const HEX_PREFIX: &str = "hex:";
+/// Number of digits in the hex encoding of a public key.
+const HEX_LEN: usize = 64;
+
+/// All kind prefixes, as listed in error messages.
+const KIND_PREFIXES: &str = "suffix:, prefix:, contains:, at:, ci:, lookalike:, hex:, re: or fp:";
+
+/// Groups of characters that are easily mistaken for each other, on top of
+/// the two cases of a letter.
+const LOOK_ALIKES: &[&[u8]] = &[b"0Oo", b"1Iil", b"2Zz", b"5Ss", b"6Gb", b"8B", b"9gq"];
+
+/// How loosely the characters of a [`Pattern::FoldedSuffix`] are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Folding {
+    CaseInsensitive,
+    LookAlike,
+}
+
+impl Folding {
+    /// The characters that count as `c`.
+    pub fn class(&self, c: char) -> CharSet {
+        let mut class = CharSet::default();
+        if !c.is_ascii() {
+            return class;
+        }
+        let c = c as u8;
+        let mut insert_both_cases = |c: u8| {
+            class.insert(c.to_ascii_lowercase());
+            class.insert(c.to_ascii_uppercase());
+        };
+
+        insert_both_cases(c);
+        if *self == Folding::LookAlike {
+            for group in LOOK_ALIKES
+                .iter()
+                .filter(|group| group.iter().any(|m| m.eq_ignore_ascii_case(&c)))
+            {
+                group.iter().for_each(|&m| insert_both_cases(m));
+            }
+        }
+        class
+    }
+
+    pub fn classes(&self, text: &str) -> Vec<CharSet> {
+        text.chars().map(|c| self.class(c)).collect()
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Folding::CaseInsensitive => "case-insensitive",
+            Folding::LookAlike => "look-alike",
+        }
+    }
 }
 
 impl Hash for Pattern {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
-            Pattern::Suffix(suffix) => suffix.hash(state),
+            Pattern::Suffix(suffix, _) => suffix.hash(state),
+            Pattern::FoldedSuffix(suffix, folding, _) => {
+                folding.hash(state);
+                suffix.hash(state)
+            }
+            Pattern::Prefix(prefix, _) => {
+                PREFIX_PREFIX.hash(state);
+                prefix.hash(state)
+            }
+            Pattern::At(offset, text, _) => {
+                offset.hash(state);
+                text.hash(state)
+            }
+            Pattern::Contains(text) => {
+                CONTAINS_PREFIX.hash(state);
+                text.hash(state)
+            }
+            Pattern::Hex(digits, _) => {
+                HEX_PREFIX.hash(state);
+                digits.hash(state)
+            }
             Pattern::Regex(regex) => regex.as_str().hash(state),
+            Pattern::Dictionary(dictionary) => {
+                dictionary.name.hash(state);
+                dictionary.min_len.hash(state);
+                dictionary.position.hash(state);
+                dictionary.ignore_case.hash(state)
+            }
+            Pattern::Fingerprint(inner) => {
+                FINGERPRINT_PREFIX.hash(state);
+                inner.hash(state)
+            }
+            Pattern::And(children) => {
+                "&".hash(state);
+                children.hash(state)
+            }
+            Pattern::Or(children) => {
+                "|".hash(state);
+                children.hash(state)
+            }
+            Pattern::Not(inner) => {
+                "!".hash(state);
+                inner.hash(state)
+            }
         }
     }
 }
@@ -24,8 +161,24 @@ impl Hash for Pattern {
 impl PartialEq for Pattern {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (Pattern::Suffix(s1), Pattern::Suffix(s2)) => s1 == s2,
+            (Pattern::Suffix(s1, _), Pattern::Suffix(s2, _)) => s1 == s2,
+            (Pattern::FoldedSuffix(s1, f1, _), Pattern::FoldedSuffix(s2, f2, _)) => {
+                s1 == s2 && f1 == f2
+            }
+            (Pattern::Prefix(p1, _), Pattern::Prefix(p2, _)) => p1 == p2,
+            (Pattern::At(o1, t1, _), Pattern::At(o2, t2, _)) => o1 == o2 && t1 == t2,
+            (Pattern::Contains(c1), Pattern::Contains(c2)) => c1 == c2,
+            (Pattern::Hex(h1, _), Pattern::Hex(h2, _)) => h1 == h2,
             (Pattern::Regex(r1), Pattern::Regex(r2)) => r1.as_str() == r2.as_str(),
+            (Pattern::Dictionary(d1), Pattern::Dictionary(d2)) => {
+                d1.name == d2.name
+                    && d1.min_len == d2.min_len
+                    && d1.position == d2.position
+                    && d1.ignore_case == d2.ignore_case
+            }
+            (Pattern::Fingerprint(p1), Pattern::Fingerprint(p2)) => p1 == p2,
+            (Pattern::And(c1), Pattern::And(c2)) | (Pattern::Or(c1), Pattern::Or(c2)) => c1 == c2,
+            (Pattern::Not(p1), Pattern::Not(p2)) => p1 == p2,
             _ => false,
         }
     }
@@ -36,29 +189,332 @@ impl Eq for Pattern {}
 impl Display for Pattern {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Pattern::Suffix(suffix) => write!(f, "Suffix: {}", suffix),
+            Pattern::Suffix(suffix, _) => write!(f, "Suffix: {}", suffix),
+            Pattern::FoldedSuffix(suffix, folding, _) => {
+                write!(f, "Suffix ({}): {}", folding.name(), suffix)
+            }
+            Pattern::Prefix(prefix, _) => write!(f, "Prefix: {}", prefix),
+            Pattern::At(offset, text, _) => write!(f, "At {}: {}", offset, text),
+            Pattern::Contains(text) => write!(f, "Contains: {}", text),
+            Pattern::Hex(digits, _) => write!(f, "Hex: {}", digits),
             Pattern::Regex(regex) => write!(f, "Regex: {}", regex.as_str()),
+            Pattern::Dictionary(dictionary) => {
+                let position = match dictionary.position {
+                    WordPosition::Tail => "end",
+                    WordPosition::Head => "start",
+                };
+                write!(
+                    f,
+                    "Dictionary {}: words of {}+ letters at the {}{}",
+                    dictionary.name,
+                    dictionary.min_len,
+                    position,
+                    if dictionary.ignore_case {
+                        ", case-insensitive"
+                    } else {
+                        ""
+                    }
+                )
+            }
+            Pattern::Fingerprint(inner) => write!(f, "Fingerprint {}", inner),
+            Pattern::And(children) => write_joined(f, children, " AND "),
+            Pattern::Or(children) => write_joined(f, children, " OR "),
+            Pattern::Not(inner) => {
+                write!(f, "NOT ")?;
+                write_operand(f, inner)
+            }
+        }
+    }
+}
+
+fn write_joined(
+    f: &mut std::fmt::Formatter<'_>,
+    children: &[Pattern],
+    separator: &str,
+) -> std::fmt::Result {
+    for (i, child) in children.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        write_operand(f, child)?;
+    }
+    Ok(())
+}
+
+/// Writes an operand of `AND`, `OR` or `NOT`, in parentheses if it is an
+/// expression itself.
+fn write_operand(f: &mut std::fmt::Formatter<'_>, pattern: &Pattern) -> std::fmt::Result {
+    match pattern {
+        Pattern::And(_) | Pattern::Or(_) => write!(f, "({})", pattern),
+        _ => write!(f, "{}", pattern),
+    }
+}
+
+/// Why a pattern given on the command line was rejected.
+#[derive(Debug)]
+pub enum PatternError {
+    Regex(regex::Error),
+    /// The pattern is malformed at the byte range `span` of `pattern`.
+    Syntax {
+        pattern: String,
+        span: Range<usize>,
+        message: String,
+    },
+    Unmatchable {
+        pattern: String,
+        reason: String,
+    },
+}
+
+impl PatternError {
+    pub fn syntax(pattern: &str, span: Range<usize>, message: String) -> Self {
+        PatternError::Syntax {
+            pattern: pattern.to_string(),
+            span,
+            message,
+        }
+    }
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternError::Regex(e) => write!(f, "{}", e),
+            PatternError::Syntax {
+                pattern,
+                span,
+                message,
+            } => {
+                // Underline the span, counting characters rather than bytes.
+                let indent = pattern[..span.start].chars().count();
+                let width = pattern[span.clone()].chars().count().max(1);
+                write!(
+                    f,
+                    "{}\n  {}\n  {}{}",
+                    message,
+                    pattern,
+                    " ".repeat(indent),
+                    "^".repeat(width)
+                )
+            }
+            PatternError::Unmatchable { pattern, reason } => {
+                write!(f, "'{}' can never match: {}", pattern, reason)
+            }
         }
     }
 }
 
+impl std::error::Error for PatternError {}
+
+impl From<regex::Error> for PatternError {
+    fn from(e: regex::Error) -> Self {
+        PatternError::Regex(e)
+    }
+}
+
 impl Pattern {
-    pub fn new(pattern: String) -> Result<Self, regex::Error> {
-        if pattern.starts_with('/') && pattern.ends_with('/') {
-            let pattern = pattern[1..pattern.len() - 1].to_string();
-            Ok(Pattern::Regex(Regex::new(&pattern)?))
+    /// Parses a pattern. The grammar is
+    ///
+    /// ```text
+    /// expression := and (("|" | "OR") and)*
+    /// and        := not (("&" | "AND") not)*
+    /// not        := ("!" | "NOT") not | "(" expression ")" | pattern
+    /// pattern    := ["fp:"] kind
+    /// kind       := "suffix:" text | "prefix:" text | "contains:" text
+    ///             | "at:" offset ":" text | "ci:" text | "lookalike:" text
+    ///             | "hex:" ["^"] digits | "re:" regex | "/" regex "/" | text
+    /// ```
+    ///
+    /// A bare `text` is a suffix and `/regex/` a regex, so `suffix:` and
+    /// `re:` are only needed for suffixes wrapped in slashes or text that
+    /// looks like a kind. A `re:` regex runs to the end of the input, so in
+    /// expressions regexes with spaces or operators go in slashes.
+    #[allow(dead_code)]
+    pub fn new(pattern: String) -> Result<Self, PatternError> {
+        Self::new_for_layout(pattern, &OPENSSH_LAYOUT)
+    }
+
+    /// Parses a pattern like [`Pattern::new`] and rejects it if no text
+    /// distributed as `layout` can ever match it.
+    pub fn new_for_layout(pattern: String, layout: &Layout) -> Result<Self, PatternError> {
+        let parsed = Self::parse(pattern.clone())?;
+        match unmatchable_reason(&parsed, layout) {
+            Some(reason) => Err(PatternError::Unmatchable { pattern, reason }),
+            None => Ok(parsed),
+        }
+    }
+
+    /// Wraps a dictionary as a pattern, rejecting it like
+    /// [`Pattern::new_for_layout`] if none of its words can ever match.
+    pub fn from_dictionary(dictionary: Dictionary, layout: &Layout) -> Result<Self, PatternError> {
+        let pattern = Pattern::Dictionary(Arc::new(dictionary));
+        match unmatchable_reason(&pattern, layout) {
+            Some(reason) => Err(PatternError::Unmatchable {
+                pattern: pattern.to_string(),
+                reason,
+            }),
+            None => Ok(pattern),
+        }
+    }
+
+    fn parse(pattern: String) -> Result<Self, PatternError> {
+        match parse_expression(&pattern, |leaf| Self::parse_leaf(leaf.to_string()))? {
+            Some(expression) => Ok(expression),
+            None => Self::parse_leaf(pattern),
+        }
+    }
+
+    fn parse_leaf(pattern: String) -> Result<Self, PatternError> {
+        match pattern.strip_prefix(FINGERPRINT_PREFIX) {
+            Some(inner) if inner.starts_with(HEX_PREFIX) => Err(PatternError::syntax(
+                &pattern,
+                0..FINGERPRINT_PREFIX.len() + HEX_PREFIX.len(),
+                "hex: patterns match the public key bytes, not the fingerprint".to_string(),
+            )),
+            Some(_) => Ok(Pattern::Fingerprint(Box::new(Self::parse_kind(
+                &pattern,
+                FINGERPRINT_PREFIX.len(),
+            )?))),
+            None => Self::parse_kind(&pattern, 0),
+        }
+    }
+
+    /// Parses the part of `pattern` from byte `start` on as a key pattern.
+    /// Errors point into the whole of `pattern`.
+    fn parse_kind(pattern: &str, start: usize) -> Result<Self, PatternError> {
+        let text = &pattern[start..];
+        let end = pattern.len();
+        let error =
+            |span: Range<usize>, message: String| PatternError::syntax(pattern, span, message);
+        let non_empty = |rest: &'_ str, kind: &str| match rest.is_empty() {
+            true => Err(error(
+                end..end,
+                format!("nothing to match after '{}'", kind),
+            )),
+            false => Ok(()),
+        };
+
+        if let Some(suffix) = text.strip_prefix(SUFFIX_PREFIX) {
+            non_empty(suffix, SUFFIX_PREFIX)?;
+            let mask = KeyMask::suffix(suffix);
+            Ok(Pattern::Suffix(suffix.to_string(), mask))
+        } else if let Some(prefix) = text.strip_prefix(PREFIX_PREFIX) {
+            non_empty(prefix, PREFIX_PREFIX)?;
+            let mask = KeyMask::at(OPENSSH_LAYOUT.variable_start(), prefix);
+            Ok(Pattern::Prefix(prefix.to_string(), mask))
+        } else if let Some(suffix) = text.strip_prefix(CASE_INSENSITIVE_PREFIX) {
+            non_empty(suffix, CASE_INSENSITIVE_PREFIX)?;
+            Ok(Self::folded_suffix(suffix, Folding::CaseInsensitive))
+        } else if let Some(suffix) = text.strip_prefix(LOOK_ALIKE_PREFIX) {
+            non_empty(suffix, LOOK_ALIKE_PREFIX)?;
+            Ok(Self::folded_suffix(suffix, Folding::LookAlike))
+        } else if let Some(text) = text.strip_prefix(CONTAINS_PREFIX) {
+            non_empty(text, CONTAINS_PREFIX)?;
+            Ok(Pattern::Contains(text.to_string()))
+        } else if let Some(rest) = text.strip_prefix(AT_PREFIX) {
+            let rest_start = start + AT_PREFIX.len();
+            let Some((offset, text)) = rest.split_once(':') else {
+                return Err(error(
+                    end..end,
+                    "expected ':' and the text after the offset, as in at:<offset>:<text>"
+                        .to_string(),
+                ));
+            };
+            let Ok(offset) = offset.parse::<usize>() else {
+                return Err(error(
+                    rest_start..rest_start + offset.len(),
+                    format!("expected a character offset, found '{}'", offset),
+                ));
+            };
+            non_empty(text, AT_PREFIX)?;
+            let mask = KeyMask::at(offset, text);
+            Ok(Pattern::At(offset, text.to_string(), mask))
+        } else if let Some(hex) = text.strip_prefix(HEX_PREFIX) {
+            let (anchored, digits) = match hex.strip_prefix('^') {
+                Some(digits) => (true, digits),
+                None => (false, hex),
+            };
+            let digits_start = end - digits.len();
+            non_empty(digits, HEX_PREFIX)?;
+            if let Some((i, c)) = digits
+                .char_indices()
+                .find(|&(_, c)| !c.is_ascii_hexdigit() && c != '.')
+            {
+                let at = digits_start + i;
+                return Err(error(
+                    at..at + c.len_utf8(),
+                    format!("'{}' is neither a hex digit nor '.'", c),
+                ));
+            }
+            if digits.len() > HEX_LEN {
+                return Err(error(
+                    digits_start + HEX_LEN..end,
+                    format!(
+                        "the public key is only {} hex digits (32 bytes) long",
+                        HEX_LEN
+                    ),
+                ));
+            }
+            let digits = digits.to_ascii_lowercase();
+            let offset = if anchored { 0 } else { HEX_LEN - digits.len() };
+            let mask = KeyMask::hex(offset, &digits);
+            let text = if anchored {
+                format!("^{}", digits)
+            } else {
+                digits
+            };
+            Ok(Pattern::Hex(text, mask))
+        } else if let Some(regex) = text.strip_prefix(REGEX_PREFIX) {
+            non_empty(regex, REGEX_PREFIX)?;
+            Ok(Pattern::Regex(Regex::new(regex)?))
+        } else if text.starts_with(FINGERPRINT_PREFIX) {
+            Err(error(
+                start..start + FINGERPRINT_PREFIX.len(),
+                "'fp:' can only come at the start of a pattern".to_string(),
+            ))
+        } else if let Some((kind, _)) = text.split_once(':')
+            && !kind.is_empty()
+            && kind.bytes().all(|c| c.is_ascii_alphanumeric())
+        {
+            // No target uses ':', so this can only be a misspelled kind.
+            Err(error(
+                start..start + kind.len() + 1,
+                format!(
+                    "unknown pattern kind '{}:', expected one of {}",
+                    kind, KIND_PREFIXES
+                ),
+            ))
+        } else if text.len() >= 2 && text.starts_with('/') && text.ends_with('/') {
+            Ok(Pattern::Regex(Regex::new(&text[1..text.len() - 1])?))
+        } else if text.is_empty() {
+            Err(error(end..end, "empty pattern".to_string()))
         } else {
-            Ok(Pattern::Suffix(pattern))
+            let mask = KeyMask::suffix(text);
+            Ok(Pattern::Suffix(text.to_string(), mask))
         }
     }
 
-    pub fn to_filename(&self) -> String {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    fn folded_suffix(suffix: &str, folding: Folding) -> Self {
+        let mask = ClassMask::suffix(&folding.classes(suffix));
+        Pattern::FoldedSuffix(suffix.to_string(), folding, mask)
+    }
+
+    /// Short name of the pattern for the names of the files keys are saved
+    /// in, e.g. `prefix_dev` or `regex_hello`.
+    pub fn filename_stem(&self) -> String {
         match self {
-            Pattern::Suffix(suffix) => format!("{}_{}", suffix, timestamp),
+            Pattern::Suffix(suffix, _) => suffix.clone(),
+            Pattern::FoldedSuffix(suffix, Folding::CaseInsensitive, _) => format!("ci_{}", suffix),
+            Pattern::FoldedSuffix(suffix, Folding::LookAlike, _) => {
+                format!("lookalike_{}", suffix)
+            }
+            Pattern::Prefix(prefix, _) => format!("prefix_{}", prefix),
+            Pattern::At(offset, text, _) => format!("at{}_{}", offset, text),
+            Pattern::Contains(text) => format!("contains_{}", text),
+            Pattern::Hex(digits, _) => match digits.strip_prefix('^') {
+                Some(digits) => format!("hexstart_{}", digits.replace('.', "x")),
+                None => format!("hex_{}", digits.replace('.', "x")),
+            },
             Pattern::Regex(regex) => {
                 let pattern = regex.as_str();
                 // Remove special characters and limit length
@@ -67,48 +523,213 @@ impl Pattern {
                     .take(20)
                     .map(|c| if c.is_alphanumeric() { c } else { '_' })
                     .collect::<String>();
-                format!("regex_{}_{}", clean, timestamp)
+                format!("regex_{}", clean)
             }
+            Pattern::Dictionary(dictionary) => format!("dict_{}", dictionary.name),
+            Pattern::Fingerprint(inner) => format!("fp_{}", inner.filename_stem()),
+            Pattern::And(children) => join_stems(children, "_and_"),
+            Pattern::Or(children) => join_stems(children, "_or_"),
+            Pattern::Not(inner) => format!("not_{}", inner.filename_stem()),
         }
     }
 
-    pub fn probability(&self) -> Option<f64> {
+    /// Probability that a random key's text, distributed as `layout`,
+    /// matches this pattern.
+    pub fn probability(&self, layout: &Layout) -> Option<f64> {
+        self.estimate(layout).map(|(probability, _)| probability)
+    }
+
+    /// Whether [`Pattern::probability`] is exact rather than estimated. It
+    /// is estimated for regexes too complex to count, which are sampled
+    /// instead, and for expressions whose parts read the same characters,
+    /// which are treated as independent.
+    pub fn is_probability_exact(&self, layout: &Layout) -> bool {
+        self.estimate(layout).is_none_or(|(_, exact)| exact)
+    }
+
+    /// Probability of a match, and whether it is exact.
+    pub(crate) fn estimate(&self, layout: &Layout) -> Option<(f64, bool)> {
+        let probability = match self {
+            Pattern::Suffix(suffix, _) => Some(layout.suffix_probability(suffix)),
+            Pattern::FoldedSuffix(suffix, folding, _) => {
+                Some(layout.class_suffix_probability(&folding.classes(suffix)))
+            }
+            Pattern::Prefix(prefix, _) => {
+                Some(layout.literal_probability(layout.variable_start(), prefix))
+            }
+            Pattern::At(offset, text, _) => Some(layout.literal_probability(*offset, text)),
+            Pattern::Contains(text) => Some(layout.contains_probability(text)),
+            // Every bit of the public key is equally likely either way.
+            Pattern::Hex(_, mask) => Some(0.5f64.powi(mask.fixed_bits() as i32)),
+            Pattern::Dictionary(dictionary) => Some(dictionary.probability(layout)),
+            Pattern::Regex(regex) => {
+                return match regex_probability(regex, layout) {
+                    RegexProbability::Exact(p) => Some((p, true)),
+                    RegexProbability::Approximate(p) => Some((p, false)),
+                    RegexProbability::Unknown => None,
+                };
+            }
+            // The fingerprint looks the same whatever key it was computed from.
+            Pattern::Fingerprint(inner) => return inner.estimate(&FINGERPRINT_LAYOUT),
+            Pattern::And(children) => return and_probability(children, layout),
+            Pattern::Or(children) => return or_probability(children, layout),
+            Pattern::Not(inner) => {
+                return inner
+                    .estimate(layout)
+                    .map(|(probability, exact)| (1.0 - probability, exact));
+            }
+        };
+        probability.map(|probability| (probability, true))
+    }
+
+    /// Whether this pattern, or any part of it, matches the fingerprint.
+    pub fn reads_fingerprint(&self) -> bool {
         match self {
-            Pattern::Suffix(suffix) => {
-                // Base64 has 64 possible characters
-                let base: f64 = 64.0;
-                // Probability is (1/64)^n where n is the length of the suffix
-                Some(1.0 / base.powi(suffix.len() as i32))
+            Pattern::Fingerprint(_) => true,
+            Pattern::And(children) | Pattern::Or(children) => {
+                children.iter().any(Pattern::reads_fingerprint)
             }
-            Pattern::Regex(_) => None, // Regex patterns are too complex to calculate probability
+            Pattern::Not(inner) => inner.reads_fingerprint(),
+            _ => false,
         }
     }
 
-    pub fn estimate_time(&self, keys_per_second: f64) -> Option<String> {
-        self.probability().map(|prob| {
-            let expected_attempts = 1.0 / prob;
-            let seconds = expected_attempts / keys_per_second;
-            if seconds > u64::MAX as f64 {
-                return "∞".to_string();
+    /// Whether this pattern, or any part of it, matches the key bytes
+    /// rather than a text.
+    pub fn reads_key_bytes(&self) -> bool {
+        match self {
+            Pattern::Hex(..) => true,
+            Pattern::And(children) | Pattern::Or(children) => {
+                children.iter().any(Pattern::reads_key_bytes)
             }
-            let duration = Duration::from_secs_f64(seconds);
-            humantime::format_duration(duration).to_string()
-        })
+            Pattern::Not(inner) => inner.reads_key_bytes(),
+            _ => false,
+        }
+    }
+
+    /// Matches a public key whose text, distributed as `layout`, is `text`,
+    /// for targets without a byte-level fast path. Hex patterns are matched
+    /// against the key bytes, everything else against the text.
+    pub fn matches_key(&self, public_key: &[u8; 32], text: &str, layout: &Layout) -> bool {
+        match self {
+            Pattern::Hex(_, mask) => mask.matches(public_key),
+            Pattern::And(children) => children
+                .iter()
+                .all(|c| c.matches_key(public_key, text, layout)),
+            Pattern::Or(children) => children
+                .iter()
+                .any(|c| c.matches_key(public_key, text, layout)),
+            Pattern::Not(inner) => !inner.matches_key(public_key, text, layout),
+            _ => self.matches_str(text, layout),
+        }
+    }
+
+    /// Matches against already encoded text distributed as `layout`.
+    /// Fingerprint patterns are matched against the text as is; use
+    /// [`Candidate`] to match them against a key. Hex patterns need the key
+    /// bytes and never match here; use [`Pattern::matches_key`].
+    pub fn matches_str(&self, text: &str, layout: &Layout) -> bool {
+        match self {
+            Pattern::Suffix(suffix, _) => text.ends_with(suffix.as_str()),
+            Pattern::FoldedSuffix(suffix, folding, _) => {
+                let n_chars = suffix.chars().count();
+                text.len() >= n_chars
+                    && text
+                        .bytes()
+                        .skip(text.len() - n_chars)
+                        .zip(suffix.chars())
+                        .all(|(actual, expected)| folding.class(expected).contains(actual))
+            }
+            Pattern::Prefix(prefix, _) => text
+                .get(layout.variable_start()..)
+                .is_some_and(|rest| rest.starts_with(prefix.as_str())),
+            Pattern::At(offset, expected, _) => {
+                text.get(*offset..*offset + expected.len()) == Some(expected.as_str())
+            }
+            Pattern::Contains(expected) => text.contains(expected.as_str()),
+            Pattern::Hex(..) => false,
+            Pattern::Regex(regex) => regex.is_match(text),
+            Pattern::Dictionary(dictionary) => dictionary
+                .find_word(|i| text.as_bytes().get(i).copied(), layout)
+                .is_some(),
+            Pattern::Fingerprint(inner) => inner.matches_str(text, layout),
+            Pattern::And(children) => children.iter().all(|c| c.matches_str(text, layout)),
+            Pattern::Or(children) => children.iter().any(|c| c.matches_str(text, layout)),
+            Pattern::Not(inner) => !inner.matches_str(text, layout),
+        }
     }
 }
 
-pub fn public_key_matches_pattern(keypair: &KeyPair, pattern: &Pattern) -> bool {
-    let openssh_pubkey = create_openssh_public_key_from_keypair(keypair);
-    let openssh_pubkey_str = openssh_pubkey.to_string();
-    let base64_part = extract_base64_from_openssh_string(&openssh_pubkey_str);
+#[allow(dead_code)]
+pub fn public_key_matches_pattern(keypair: &Ed25519KeyPair, pattern: &Pattern) -> bool {
+    Candidate::new(keypair).matches(pattern)
+}
 
-    match pattern {
-        Pattern::Suffix(suffix) => base64_part.ends_with(suffix),
-        Pattern::Regex(regex) => regex.is_match(base64_part),
+/// A key pair being checked against the patterns of the OpenSSH target. The
+/// text renderings of the key are computed at most once, and only if a
+/// pattern needs them.
+pub struct Candidate<'a> {
+    keypair: &'a Ed25519KeyPair,
+    base64: OnceCell<String>,
+    fingerprint: OnceCell<String>,
+}
+
+impl<'a> Candidate<'a> {
+    pub fn new(keypair: &'a Ed25519KeyPair) -> Self {
+        Self {
+            keypair,
+            base64: OnceCell::new(),
+            fingerprint: OnceCell::new(),
+        }
+    }
+
+    pub fn matches(&self, pattern: &Pattern) -> bool {
+        match pattern {
+            Pattern::Suffix(_, mask)
+            | Pattern::Prefix(_, mask)
+            | Pattern::At(_, _, mask)
+            | Pattern::Hex(_, mask) => mask.matches(self.keypair.public_key.as_bytes()),
+            Pattern::FoldedSuffix(_, _, mask) => mask.matches(self.keypair.public_key.as_bytes()),
+            Pattern::Dictionary(dictionary) => self.word(dictionary).is_some(),
+            Pattern::Contains(_) | Pattern::Regex(_) => {
+                pattern.matches_str(self.base64(), &OPENSSH_LAYOUT)
+            }
+            Pattern::Fingerprint(inner) => {
+                inner.matches_str(self.fingerprint(), &FINGERPRINT_LAYOUT)
+            }
+            Pattern::And(children) => children.iter().all(|c| self.matches(c)),
+            Pattern::Or(children) => children.iter().any(|c| self.matches(c)),
+            Pattern::Not(inner) => !self.matches(inner),
+        }
+    }
+
+    /// The word of `dictionary` found in the key, read straight from the key
+    /// bytes.
+    pub fn word<'d>(&self, dictionary: &'d Dictionary) -> Option<&'d str> {
+        let public_key = self.keypair.public_key.as_bytes();
+        dictionary.find_word(
+            |i| (i < BASE64_LEN).then(|| BASE64_ALPHABET[sextet_at(public_key, i) as usize]),
+            &OPENSSH_LAYOUT,
+        )
+    }
+
+    pub fn base64(&self) -> &str {
+        self.base64.get_or_init(|| {
+            let openssh_pubkey = create_openssh_public_key_from_keypair(self.keypair);
+            extract_base64_from_openssh_string(&openssh_pubkey.to_string()).to_string()
+        })
+    }
+
+    fn fingerprint(&self) -> &str {
+        self.fingerprint.get_or_init(|| {
+            openssh_fingerprint(&create_openssh_public_key_from_keypair(self.keypair))
+        })
     }
 }
 
-fn create_openssh_public_key_from_keypair(keypair: &KeyPair) -> ssh_key::public::PublicKey {
+pub fn create_openssh_public_key_from_keypair(
+    keypair: &Ed25519KeyPair,
+) -> ssh_key::public::PublicKey {
     let public_bytes = keypair.public_key.to_bytes();
     let ed25519_public = Ed25519PublicKey::try_from(&public_bytes[..]).unwrap();
     ssh_key::public::PublicKey::from(ed25519_public)
@@ -117,3 +738,320 @@ fn create_openssh_public_key_from_keypair(keypair: &KeyPair) -> ssh_key::public:
 fn extract_base64_from_openssh_string(openssh_string: &str) -> &str {
     openssh_string.split_whitespace().nth(1).unwrap_or("")
 }
+
+/// Expected time to find a key that matches with `probability`, at
+/// `keys_per_second`.
+pub fn estimate_time(probability: f64, keys_per_second: f64) -> String {
+    let seconds = 1.0 / probability / keys_per_second;
+    if seconds > u64::MAX as f64 {
+        return "∞".to_string();
+    }
+    humantime::format_duration(Duration::from_secs_f64(seconds)).to_string()
+}
+
+fn join_stems(children: &[Pattern], separator: &str) -> String {
+    children
+        .iter()
+        .map(Pattern::filename_stem)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::keypair::generate_keypair_batch;
+
+    fn pattern(text: &str) -> Pattern {
+        Pattern::new(text.to_string()).unwrap()
+    }
+
+    /// Checks the byte-level match against the text of the key, so both agree
+    /// on `expected`.
+    fn assert_matches(candidate: &Candidate, pattern: &Pattern, expected: bool) {
+        let text = candidate.base64();
+        assert_eq!(
+            candidate.matches(pattern),
+            expected,
+            "{} in {}",
+            pattern,
+            text
+        );
+        if !pattern.reads_key_bytes() {
+            assert_eq!(
+                pattern.matches_str(text, &OPENSSH_LAYOUT),
+                expected,
+                "{} in {}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    /// `text` with the character at `i` replaced by a different one.
+    fn replace_at(text: &str, i: usize) -> String {
+        let c = match text.as_bytes()[i] {
+            b'A' => 'B',
+            _ => 'A',
+        };
+        format!("{}{}{}", &text[..i], c, &text[i + 1..])
+    }
+
+    #[test]
+    fn position_kinds_parse() {
+        assert!(matches!(pattern("abc"), Pattern::Suffix(s, _) if s == "abc"));
+        assert!(matches!(pattern("suffix:abc"), Pattern::Suffix(s, _) if s == "abc"));
+        assert!(matches!(pattern("prefix:Abc"), Pattern::Prefix(s, _) if s == "Abc"));
+        assert!(matches!(pattern("contains:abc"), Pattern::Contains(s) if s == "abc"));
+        assert!(matches!(pattern("at:30:abc"), Pattern::At(30, s, _) if s == "abc"));
+    }
+
+    #[test]
+    fn position_kinds_match_where_they_say() {
+        let start = OPENSSH_LAYOUT.variable_start();
+        for keypair in generate_keypair_batch(20) {
+            let candidate = Candidate::new(&keypair);
+            let text = candidate.base64().to_string();
+
+            let prefix = &text[start..start + 3];
+            assert_matches(&candidate, &pattern(&format!("prefix:{}", prefix)), true);
+            let other = replace_at(prefix, 2);
+            assert_matches(&candidate, &pattern(&format!("prefix:{}", other)), false);
+
+            let at = &text[40..44];
+            assert_matches(&candidate, &pattern(&format!("at:40:{}", at)), true);
+            let other = replace_at(at, 0);
+            assert_matches(&candidate, &pattern(&format!("at:40:{}", other)), false);
+
+            assert_matches(&candidate, &pattern(&format!("contains:{}", at)), true);
+        }
+    }
+
+    #[test]
+    fn contains_finds_text_anywhere() {
+        let layout = &OPENSSH_LAYOUT;
+        let contains = pattern("contains:dev");
+        assert!(contains.matches_str("AAAAC3NzaC1lZDI1NTE5AAAAIdevxyz", layout));
+        assert!(contains.matches_str("AAAAC3NzaC1lZDI1NTE5AAAAIxyzdev", layout));
+        assert!(!contains.matches_str("AAAAC3NzaC1lZDI1NTE5AAAAIxdexv", layout));
+    }
+
+    fn class(folding: Folding, c: char) -> String {
+        folding.class(c).iter().map(char::from).collect()
+    }
+
+    #[test]
+    fn folding_classes() {
+        assert_eq!(class(Folding::CaseInsensitive, 'a'), "Aa");
+        assert_eq!(class(Folding::CaseInsensitive, '0'), "0");
+        assert_eq!(class(Folding::LookAlike, 'o'), "0Oo");
+        assert_eq!(class(Folding::LookAlike, 'L'), "1ILil");
+        assert_eq!(class(Folding::LookAlike, 'b'), "68BGbg");
+        assert_eq!(class(Folding::LookAlike, '+'), "+");
+        assert_eq!(class(Folding::LookAlike, 'é'), "");
+    }
+
+    #[test]
+    fn folded_suffixes_parse() {
+        assert!(matches!(
+            pattern("ci:Dev"),
+            Pattern::FoldedSuffix(s, Folding::CaseInsensitive, _) if s == "Dev"
+        ));
+        assert!(matches!(
+            pattern("lookalike:c0de"),
+            Pattern::FoldedSuffix(s, Folding::LookAlike, _) if s == "c0de"
+        ));
+    }
+
+    #[test]
+    fn folded_suffixes_match_any_character_of_their_class() {
+        let layout = &OPENSSH_LAYOUT;
+        let ci = pattern("ci:Dev");
+        assert!(ci.matches_str("AAAAxdEV", layout));
+        assert!(ci.matches_str("AAAAxdev", layout));
+        assert!(!ci.matches_str("AAAAxdew", layout));
+        let look_alike = pattern("lookalike:c0de");
+        assert!(look_alike.matches_str("AAAAxCOdE", layout));
+        assert!(look_alike.matches_str("AAAAxcode", layout));
+        assert!(!look_alike.matches_str("AAAAxcQde", layout));
+    }
+
+    /// Rewrites each key's own suffix with other members of the classes, so
+    /// the byte-level class mask must accept it, and then with the last
+    /// character outside its class, which it must not.
+    #[test]
+    fn class_masks_agree_with_the_text() {
+        for folding in [Folding::CaseInsensitive, Folding::LookAlike] {
+            for keypair in generate_keypair_batch(50) {
+                let candidate = Candidate::new(&keypair);
+                let text = candidate.base64().to_string();
+                let own = &text[text.len() - 6..];
+                let folded: String = own
+                    .chars()
+                    .map(|c| folding.class(c).iter().last().map_or(c, char::from))
+                    .collect();
+                assert_matches(&candidate, &Pattern::folded_suffix(&folded, folding), true);
+
+                let last = own.chars().last().unwrap();
+                let outside = BASE64_ALPHABET
+                    .iter()
+                    .map(|&c| c as char)
+                    .find(|&c| !folding.class(last).contains(c as u8))
+                    .unwrap();
+                let other = format!("{}{}", &folded[..5], outside);
+                assert_matches(&candidate, &Pattern::folded_suffix(&other, folding), false);
+            }
+        }
+    }
+
+    /// The span and message of the syntax error `text` is rejected with.
+    fn syntax_error(text: &str) -> (Range<usize>, String) {
+        match Pattern::new(text.to_string()) {
+            Err(PatternError::Syntax { span, message, .. }) => (span, message),
+            other => panic!("{:?} for {}", other, text),
+        }
+    }
+
+    #[test]
+    fn kind_prefixes_parse() {
+        assert!(matches!(pattern("re:^AAAA"), Pattern::Regex(r) if r.as_str() == "^AAAA"));
+        assert!(matches!(pattern("/ab+/"), Pattern::Regex(r) if r.as_str() == "ab+"));
+        assert!(matches!(pattern("suffix:/ab/"), Pattern::Suffix(s, _) if s == "/ab/"));
+        // Only the first `:` of a kind counts, even though no key matches.
+        assert!(matches!(
+            Pattern::parse("re:a:b".to_string()).unwrap(),
+            Pattern::Regex(r) if r.as_str() == "a:b"
+        ));
+        match pattern("fp:prefix:ab") {
+            Pattern::Fingerprint(inner) => {
+                assert!(matches!(*inner, Pattern::Prefix(s, _) if s == "ab"))
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_kinds_point_at_the_bad_part() {
+        assert_eq!(
+            syntax_error("suffix:"),
+            (7..7, "nothing to match after 'suffix:'".to_string())
+        );
+        assert_eq!(
+            syntax_error("fp:contains:"),
+            (12..12, "nothing to match after 'contains:'".to_string())
+        );
+        assert_eq!(
+            syntax_error("at:5"),
+            (
+                4..4,
+                "expected ':' and the text after the offset, as in at:<offset>:<text>".to_string()
+            )
+        );
+        assert_eq!(
+            syntax_error("at:x:abc"),
+            (3..4, "expected a character offset, found 'x'".to_string())
+        );
+        assert_eq!(
+            syntax_error("fp:fp:abc"),
+            (
+                3..6,
+                "'fp:' can only come at the start of a pattern".to_string()
+            )
+        );
+        assert_eq!(
+            syntax_error("fp:sufix:abc"),
+            (
+                3..9,
+                format!(
+                    "unknown pattern kind 'sufix:', expected one of {}",
+                    KIND_PREFIXES
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn syntax_errors_underline_the_span() {
+        let error = Pattern::new("at:x:abc".to_string()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected a character offset, found 'x'\n  at:x:abc\n     ^"
+        );
+    }
+
+    #[test]
+    fn hex_patterns_parse() {
+        assert!(matches!(pattern("hex:^C0ffee"), Pattern::Hex(s, _) if s == "^c0ffee"));
+        assert!(matches!(pattern("hex:be.f"), Pattern::Hex(s, _) if s == "be.f"));
+        // Wildcards fix no bits.
+        let probability = pattern("hex:^a..b").probability(&OPENSSH_LAYOUT).unwrap();
+        assert_eq!(probability, 0.5f64.powi(8));
+    }
+
+    #[test]
+    fn hex_masks_match_the_key_bytes() {
+        for keypair in generate_keypair_batch(20) {
+            let candidate = Candidate::new(&keypair);
+            let key = keypair.public_key.as_bytes();
+            let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+            let text = candidate.base64().to_string();
+
+            let head = &hex[..7];
+            let tail = &hex[HEX_LEN - 7..];
+            let wildcard = format!("{}..{}", &hex[..2], &hex[4..7]);
+            for (digits, expected) in [
+                (format!("^{}", head), true),
+                (tail.to_string(), true),
+                (format!("^{}", wildcard), true),
+                (format!("^{}", &hex[1..8]), hex[1..8] == hex[..7]),
+                (format!("^{}", replace_hex(head, 6)), false),
+                (replace_hex(tail, 0), false),
+            ] {
+                let hex_pattern = pattern(&format!("hex:{}", digits));
+                assert_matches(&candidate, &hex_pattern, expected);
+                assert_eq!(
+                    hex_pattern.matches_key(key, &text, &OPENSSH_LAYOUT),
+                    expected,
+                    "{} in {}",
+                    digits,
+                    hex
+                );
+            }
+        }
+    }
+
+    /// `digits` with the digit at `i` replaced by a different one.
+    fn replace_hex(digits: &str, i: usize) -> String {
+        let c = match digits.as_bytes()[i] {
+            b'0' => '1',
+            _ => '0',
+        };
+        format!("{}{}{}", &digits[..i], c, &digits[i + 1..])
+    }
+
+    #[test]
+    fn malformed_hex_is_rejected() {
+        assert_eq!(
+            syntax_error("hex:12g4"),
+            (6..7, "'g' is neither a hex digit nor '.'".to_string())
+        );
+        assert_eq!(
+            syntax_error("hex:^"),
+            (5..5, "nothing to match after 'hex:'".to_string())
+        );
+        assert_eq!(
+            syntax_error(&format!("hex:{}", "0".repeat(66))),
+            (
+                68..70,
+                "the public key is only 64 hex digits (32 bytes) long".to_string()
+            )
+        );
+        assert_eq!(
+            syntax_error("fp:hex:ab"),
+            (
+                0..7,
+                "hex: patterns match the public key bytes, not the fingerprint".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::core::pattern::Pattern;
+
+/// Index of the root node, which is never anyone's child, so `ROOT` doubles
+/// as "no child" in the transition table.
+const ROOT: usize = 0;
+
+/// The patterns being searched for, with all literal suffixes compiled into a
+/// trie over the reversed text. Checking a key walks its last characters once
+/// instead of comparing every suffix, so thousands of suffixes cost about the
+/// same as one.
+#[derive(Debug, Clone)]
+pub struct PatternSet {
+    patterns: Vec<Pattern>,
+    suffixes: SuffixTrie,
+    /// Indices of the patterns not in the trie, in order.
+    others: Vec<usize>,
+}
+
+impl PatternSet {
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        let mut suffixes = SuffixTrie::default();
+        let mut others = Vec::new();
+
+        for (index, pattern) in patterns.iter().enumerate() {
+            match pattern {
+                Pattern::Suffix(suffix, _) => suffixes.insert(suffix.as_bytes(), index),
+                _ => others.push(index),
+            }
+        }
+
+        Self {
+            patterns,
+            suffixes,
+            others,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// The same set without `pattern`.
+    pub fn without(&self, pattern: &Pattern) -> Self {
+        Self::new(
+            self.patterns
+                .iter()
+                .filter(|p| *p != pattern)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Returns the first pattern, in the order given, that matches.
+    /// `char_from_end(i)` is the `i`-th character of the text counted from
+    /// its end, or `None` past its start; `matches` checks a pattern that is
+    /// not a literal suffix.
+    pub fn find_match(
+        &self,
+        char_from_end: impl Fn(usize) -> Option<u8>,
+        matches: impl Fn(&Pattern) -> bool,
+    ) -> Option<&Pattern> {
+        let suffix_match = self.suffixes.find(char_from_end);
+        let other_match = self
+            .others
+            .iter()
+            .copied()
+            .take_while(|&index| suffix_match.is_none_or(|suffix| index < suffix))
+            .find(|&index| matches(&self.patterns[index]));
+
+        other_match
+            .or(suffix_match)
+            .map(|index| &self.patterns[index])
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SuffixTrie {
+    /// Child per ASCII character for every node, `ROOT` if there is none.
+    children: Vec<[u32; 128]>,
+    /// Lowest index of a pattern ending at each node.
+    terminal: Vec<Option<usize>>,
+}
+
+impl Default for SuffixTrie {
+    fn default() -> Self {
+        Self {
+            children: vec![[ROOT as u32; 128]],
+            terminal: vec![None],
+        }
+    }
+}
+
+impl SuffixTrie {
+    fn insert(&mut self, suffix: &[u8], index: usize) {
+        let mut node = ROOT;
+        for &c in suffix.iter().rev() {
+            // Validation only lets through characters of the layout alphabet.
+            let c = c as usize & 0x7f;
+            node = match self.children[node][c] as usize {
+                ROOT => {
+                    self.children.push([ROOT as u32; 128]);
+                    self.terminal.push(None);
+                    let child = self.children.len() - 1;
+                    self.children[node][c] = child as u32;
+                    child
+                }
+                child => child,
+            };
+        }
+        let terminal = &mut self.terminal[node];
+        *terminal = Some(terminal.map_or(index, |existing| existing.min(index)));
+    }
+
+    fn find(&self, char_from_end: impl Fn(usize) -> Option<u8>) -> Option<usize> {
+        let mut best = self.terminal[ROOT];
+        let mut node = ROOT;
+        for i in 0.. {
+            let Some(c) = char_from_end(i) else {
+                break;
+            };
+            node = match self.children[node].get(c as usize) {
+                Some(&child) if child as usize != ROOT => child as usize,
+                _ => break,
+            };
+            if let Some(index) = self.terminal[node] {
+                best = Some(best.map_or(index, |best| best.min(index)));
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::core::layout::OPENSSH_LAYOUT;
+
+    fn set(patterns: &[&str]) -> PatternSet {
+        PatternSet::new(
+            patterns
+                .iter()
+                .map(|p| Pattern::new(p.to_string()).unwrap())
+                .collect(),
+        )
+    }
+
+    /// The pattern `set` finds in `text`, as it is displayed.
+    fn find(set: &PatternSet, text: &str) -> Option<String> {
+        set.find_match(
+            |i| text.as_bytes().iter().rev().nth(i).copied(),
+            |p| p.matches_str(text, &OPENSSH_LAYOUT),
+        )
+        .map(Pattern::to_string)
+    }
+
+    #[test]
+    fn first_pattern_in_order_wins() {
+        let text = "AAAAabcxyz";
+        assert_eq!(
+            find(&set(&["xyz", "contains:abc", "yz"]), text).unwrap(),
+            "Suffix: xyz"
+        );
+        assert_eq!(
+            find(&set(&["contains:abc", "xyz"]), text).unwrap(),
+            "Contains: abc"
+        );
+        // The order decides, not the length or which suffix the trie
+        // reaches first.
+        assert_eq!(find(&set(&["yz", "xyz"]), text).unwrap(), "Suffix: yz");
+        assert_eq!(find(&set(&["xyz", "yz"]), text).unwrap(), "Suffix: xyz");
+        assert_eq!(
+            find(&set(&["wxyz", "xyz", "z"]), text).unwrap(),
+            "Suffix: xyz"
+        );
+        assert_eq!(find(&set(&["wxyz", "contains:abd"]), text), None);
+    }
+
+    #[test]
+    fn suffixes_longer_than_the_text_do_not_match() {
+        assert_eq!(
+            find(&set(&["AAAAxyz", "xyz"]), "Axyz").unwrap(),
+            "Suffix: xyz"
+        );
+        assert_eq!(find(&set(&["AAAAxyz"]), "Axyz"), None);
+    }
+
+    #[test]
+    fn patterns_after_a_matching_suffix_are_not_checked() {
+        let patterns = set(&["contains:abc", "xyz", "contains:xyz"]);
+        let checked = Cell::new(0);
+        let found = patterns.find_match(
+            |i| b"AAAAxyz".iter().rev().nth(i).copied(),
+            |_| {
+                checked.set(checked.get() + 1);
+                false
+            },
+        );
+        assert_eq!(found.unwrap().to_string(), "Suffix: xyz");
+        assert_eq!(checked.get(), 1);
+    }
+
+    #[test]
+    fn without_keeps_the_order_of_the_rest() {
+        let patterns = set(&["yz", "contains:abc", "xyz"]);
+        let without = patterns.without(&Pattern::new("yz".to_string()).unwrap());
+        assert_eq!(find(&without, "AAAAabcxyz").unwrap(), "Contains: abc");
+        assert_eq!(find(&without, "AAAAxyz").unwrap(), "Suffix: xyz");
+        assert_eq!(find(&without, "AAAAyz"), None);
+    }
+}
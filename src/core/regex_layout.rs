@@ -0,0 +1,197 @@
+use rand::thread_rng;
+use regex::Regex;
+use regex_automata::dfa::Automaton;
+use regex_automata::dfa::dense::{self, DFA};
+use regex_automata::util::primitives::StateID;
+use regex_automata::util::start;
+use regex_automata::{Anchored, MatchKind};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::core::layout::Layout;
+
+/// Upper bound for the memory used by the DFA of a single regex. Anything
+/// larger is treated as too complex to analyze.
+const DFA_SIZE_LIMIT: usize = 16 * (1 << 20);
+
+/// Random texts drawn when a regex is too complex to count, stopping early
+/// once `SAMPLE_TIME` has passed.
+const MAX_SAMPLES: u32 = 1_000_000;
+const SAMPLE_TIME: Duration = Duration::from_secs(1);
+
+/// Probabilities are asked for on every progress update, so they are
+/// computed once per regex and layout. Layouts are told apart by their
+/// contents, since many of them share a name.
+static PROBABILITY_CACHE: LazyLock<Mutex<HashMap<(String, Layout), RegexProbability>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Probability that a random text matches a regex.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegexProbability {
+    /// Counted exactly over the regex automaton.
+    Exact(f64),
+    /// Estimated from random samples because the regex is too complex to
+    /// count.
+    Approximate(f64),
+    /// Too complex to count and too rare to show up in the samples.
+    Unknown,
+}
+
+/// Compiles `regex` into a DFA that can be stepped over the positions of a
+/// [`Layout`] one character at a time. Returns `None` if the regex uses
+/// features the DFA does not support or the DFA would be too large.
+fn build_dfa(regex: &Regex) -> Option<DFA<Vec<u32>>> {
+    dense::Builder::new()
+        .configure(
+            dense::Config::new()
+                .match_kind(MatchKind::All)
+                // Layout texts are ASCII, so the non-ASCII bytes this gives
+                // up on never occur.
+                .unicode_word_boundary(true)
+                .dfa_size_limit(Some(DFA_SIZE_LIMIT))
+                .determinize_size_limit(Some(DFA_SIZE_LIMIT)),
+        )
+        .build(regex.as_str())
+        .ok()
+}
+
+fn start_state(dfa: &DFA<Vec<u32>>) -> Option<StateID> {
+    dfa.start_state(&start::Config::new().anchored(Anchored::No))
+        .ok()
+}
+
+/// Whether any text distributed as `layout` can match `regex`, or `None` if
+/// the regex is too complex to tell.
+pub fn regex_can_match(regex: &Regex, layout: &Layout) -> Option<bool> {
+    let dfa = build_dfa(regex)?;
+    let mut states = BTreeSet::from([start_state(&dfa)?]);
+
+    for i in 0..layout.len() {
+        let mut next_states = BTreeSet::new();
+        for &state in &states {
+            for c in layout.position(i).iter() {
+                let next = dfa.next_state(state, c);
+                // Matches are reported one character late, so this means
+                // the text so far already contains a match.
+                if dfa.is_match_state(next) {
+                    return Some(true);
+                }
+                if dfa.is_quit_state(next) {
+                    return None;
+                }
+                if !dfa.is_dead_state(next) {
+                    next_states.insert(next);
+                }
+            }
+        }
+        if next_states.is_empty() {
+            return Some(false);
+        }
+        states = next_states;
+    }
+
+    Some(
+        states
+            .iter()
+            .any(|&state| dfa.is_match_state(dfa.next_eoi_state(state))),
+    )
+}
+
+/// Probability that a random text distributed as `layout` matches `regex`.
+pub fn regex_probability(regex: &Regex, layout: &Layout) -> RegexProbability {
+    let key = (regex.as_str().to_string(), layout.clone());
+    if let Some(&probability) = PROBABILITY_CACHE.lock().unwrap().get(&key) {
+        return probability;
+    }
+
+    let probability = match exact_probability(regex, layout) {
+        Some(probability) => RegexProbability::Exact(probability),
+        None => sampled_probability(regex, layout),
+    };
+    PROBABILITY_CACHE.lock().unwrap().insert(key, probability);
+    probability
+}
+
+/// Runs the DFA over the per-position character distributions, keeping the
+/// probability of being in each state without having matched yet.
+fn exact_probability(regex: &Regex, layout: &Layout) -> Option<f64> {
+    let dfa = build_dfa(regex)?;
+    let mut distribution = HashMap::from([(start_state(&dfa)?, 1.0)]);
+    let mut matched = 0.0;
+
+    for i in 0..layout.len() {
+        let set = layout.position(i);
+        let weight = 1.0 / set.len() as f64;
+        let mut next_distribution = HashMap::with_capacity(distribution.len());
+        for (&state, &p) in &distribution {
+            for c in set.iter() {
+                let next = dfa.next_state(state, c);
+                if dfa.is_match_state(next) {
+                    matched += p * weight;
+                } else if dfa.is_quit_state(next) {
+                    return None;
+                } else if !dfa.is_dead_state(next) {
+                    *next_distribution.entry(next).or_insert(0.0) += p * weight;
+                }
+            }
+        }
+        distribution = next_distribution;
+    }
+
+    let matched_at_end: f64 = distribution
+        .iter()
+        .filter(|&(&state, _)| dfa.is_match_state(dfa.next_eoi_state(state)))
+        .map(|(_, &p)| p)
+        .sum();
+
+    Some(matched + matched_at_end)
+}
+
+fn sampled_probability(regex: &Regex, layout: &Layout) -> RegexProbability {
+    let mut rng = thread_rng();
+    let start = Instant::now();
+    let mut samples = 0;
+    let mut hits = 0;
+
+    while samples < MAX_SAMPLES && start.elapsed() < SAMPLE_TIME {
+        for _ in 0..1000 {
+            if regex.is_match(&layout.sample(&mut rng)) {
+                hits += 1;
+            }
+        }
+        samples += 1000;
+    }
+
+    match hits {
+        0 => RegexProbability::Unknown,
+        hits => RegexProbability::Approximate(hits as f64 / samples as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::layout::{BASE64_ALPHABET, BlobByte};
+
+    #[test]
+    fn layouts_with_the_same_name_are_cached_apart() {
+        // The first character is always `A` in one and random in the other.
+        let fixed = Layout::encoded(
+            "public key",
+            &[BlobByte::Fixed(0), BlobByte::Random],
+            BASE64_ALPHABET,
+        );
+        let random = Layout::encoded("public key", &[BlobByte::Random; 2], BASE64_ALPHABET);
+        let regex = Regex::new("^A").unwrap();
+
+        assert_eq!(
+            regex_probability(&regex, &fixed),
+            RegexProbability::Exact(1.0)
+        );
+        assert_eq!(
+            regex_probability(&regex, &random),
+            RegexProbability::Exact(1.0 / 64.0)
+        );
+    }
+}
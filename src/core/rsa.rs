@@ -0,0 +1,377 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore};
+use rsa::pkcs1v15::{SigningKey, VerifyingKey};
+use rsa::signature::{RandomizedSigner, Verifier};
+use rsa::traits::{PrivateKeyParts, PublicKeyParts};
+use rsa::{BigUint, Pkcs1v15Encrypt, RsaPrivateKey};
+use sha2::Sha256;
+use ssh_key::private::{PrivateKey, RsaKeypair};
+use std::cell::OnceCell;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::backend::{BatchKeyPair, KeyBackend};
+use crate::core::file_io::{KeyFileOptions, remove_openssh_key, write_openssh_key};
+use crate::core::fingerprint::{FINGERPRINT_LAYOUT, blob_fingerprint};
+use crate::core::layout::{BASE64_ALPHABET, BlobByte, Layout};
+use crate::core::pattern::Pattern;
+use crate::core::pattern_set::PatternSet;
+
+pub const DEFAULT_BITS: u64 = 3072;
+
+/// Public exponents are odd and between 2^30 and 2^31. Staying below 2^31
+/// keeps the keys usable by implementations that store `e` in an `int`, like
+/// Go's, and a fixed top bit keeps the exponent 4 bytes long, so the
+/// modulus after it never shifts in the base64 text.
+const MIN_EXPONENT: u32 = 0x4000_0001;
+const MAX_EXPONENT: u32 = 0x7fff_ffff;
+
+/// Where the exponent starts in the key blob: after the `ssh-rsa` string and
+/// the length of the exponent.
+const EXPONENT_OFFSET: usize = 4 + 7 + 4;
+
+/// The primes of an RSA key. Generating them takes far longer than
+/// checking a key against patterns, so a run generates them once and only
+/// varies the public exponent. Whoever holds one private key of a modulus
+/// can factor it, so a run saves only one key.
+pub struct RsaModulus {
+    private_key: RsaPrivateKey,
+    /// `p - 1` and `q - 1` as big-endian 32-bit words. An exponent is usable
+    /// if it shares no factor with either.
+    totient_factors: [Vec<u32>; 2],
+    /// The key blob with the exponent left zero.
+    blob: Vec<u8>,
+    layout: Layout,
+}
+
+impl RsaModulus {
+    pub fn generate(bits: usize) -> rsa::Result<Self> {
+        let private_key = RsaPrivateKey::new(&mut OsRng, bits)?;
+        let one = BigUint::from(1u32);
+        let totient_factors = [0, 1].map(|i| to_words(&(&private_key.primes()[i] - &one)));
+
+        let mut blob = Vec::new();
+        write_string(&mut blob, b"ssh-rsa");
+        write_string(&mut blob, &[0; 4]);
+        write_mpint(&mut blob, &private_key.n().to_bytes_be());
+
+        let mut layout_blob: Vec<BlobByte> = blob.iter().map(|&b| BlobByte::Fixed(b)).collect();
+        layout_blob[EXPONENT_OFFSET..EXPONENT_OFFSET + 4].copy_from_slice(&[
+            BlobByte::Masked {
+                mask: 0xc0,
+                value: 0x40,
+            },
+            BlobByte::Random,
+            BlobByte::Random,
+            BlobByte::Masked {
+                mask: 0x01,
+                value: 0x01,
+            },
+        ]);
+        let layout = Layout::encoded("public key", &layout_blob, BASE64_ALPHABET);
+
+        Ok(Self {
+            private_key,
+            totient_factors,
+            blob,
+            layout,
+        })
+    }
+
+    pub fn bits(&self) -> usize {
+        self.private_key.n().bits()
+    }
+
+    /// Layout of the base64 key blob. Only the characters of the exponent
+    /// vary, the modulus after it is the same in every key.
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Whether `exponent` has an inverse modulo `lcm(p - 1, q - 1)`, i.e.
+    /// whether there is a private exponent for it.
+    fn accepts(&self, exponent: u32) -> bool {
+        self.totient_factors
+            .iter()
+            .all(|words| gcd(remainder(words, exponent), exponent as u64) == 1)
+    }
+}
+
+impl fmt::Debug for RsaModulus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RsaModulus({} bits, redacted)", self.bits())
+    }
+}
+
+/// An RSA key pair: the modulus of the run and one public exponent. The
+/// private exponent is only computed for keys that are written.
+#[derive(Debug)]
+pub struct RsaKeyPair {
+    pub modulus: &'static RsaModulus,
+    pub exponent: u32,
+}
+
+impl RsaKeyPair {
+    /// The key blob in the `.pub` file.
+    pub fn blob(&self) -> Vec<u8> {
+        let mut blob = self.modulus.blob.clone();
+        blob[EXPONENT_OFFSET..EXPONENT_OFFSET + 4].copy_from_slice(&self.exponent.to_be_bytes());
+        blob
+    }
+
+    /// The base64 key blob as it appears in the `.pub` file, without its `=`
+    /// padding, which is what patterns match.
+    pub fn text(&self) -> String {
+        STANDARD_NO_PAD.encode(self.blob())
+    }
+
+    /// Computes the private exponent and checks the whole key before it is
+    /// written: it must encrypt and decrypt, sign and verify, and give the
+    /// public key that was matched.
+    pub fn to_ssh_keypair(&self) -> io::Result<RsaKeypair> {
+        let primes = self.modulus.private_key.primes();
+        let private_key = RsaPrivateKey::from_p_q(
+            primes[0].clone(),
+            primes[1].clone(),
+            BigUint::from(self.exponent),
+        )
+        .map_err(invalid_key)?;
+        check_round_trip(&private_key)?;
+
+        let keypair = RsaKeypair::try_from(&private_key).map_err(invalid_key)?;
+        let public_key = ssh_key::public::PublicKey::from(keypair.public.clone());
+        if public_key.to_bytes().map_err(invalid_key)? != self.blob() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the RSA key does not match the public key",
+            ));
+        }
+        Ok(keypair)
+    }
+
+    /// Returns the first pattern the key pair matches.
+    pub fn find_match<'a>(&self, patterns: &'a PatternSet) -> Option<&'a Pattern> {
+        let candidate = RsaCandidate::new(self);
+        let text = candidate.text.as_bytes();
+        patterns.find_match(
+            |i| text.iter().rev().nth(i).copied(),
+            |p| candidate.matches(p),
+        )
+    }
+}
+
+/// The primes stay with the modulus, so there is nothing to wipe or lock.
+impl BatchKeyPair for RsaKeyPair {
+    fn take(&mut self) -> RsaKeyPair {
+        self.duplicate()
+    }
+
+    fn duplicate(&self) -> RsaKeyPair {
+        RsaKeyPair {
+            modulus: self.modulus,
+            exponent: self.exponent,
+        }
+    }
+
+    fn lock(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// OpenSSH RSA keys sharing one modulus. Patterns match the base64 key blob
+/// or the fingerprint.
+#[derive(Debug, Clone, Copy)]
+pub struct RsaBackend {
+    pub modulus: &'static RsaModulus,
+}
+
+impl RsaBackend {
+    /// Generates the modulus of the search. It lives as long as the process,
+    /// since every key pair of the search refers to it.
+    pub fn generate(bits: usize) -> rsa::Result<Self> {
+        let modulus = RsaModulus::generate(bits)?;
+        Ok(Self {
+            modulus: Box::leak(Box::new(modulus)),
+        })
+    }
+}
+
+impl KeyBackend for RsaBackend {
+    type KeyPair = RsaKeyPair;
+
+    fn layout(&self) -> &'static Layout {
+        self.modulus.layout()
+    }
+
+    fn generate_batch(&self, batch_size: usize) -> Vec<RsaKeyPair> {
+        generate_rsa_batch(self.modulus, batch_size)
+    }
+
+    fn text(&self, keypair: &RsaKeyPair) -> String {
+        keypair.text()
+    }
+
+    fn find_match<'a>(
+        &self,
+        keypair: &RsaKeyPair,
+        patterns: &'a PatternSet,
+    ) -> Option<&'a Pattern> {
+        keypair.find_match(patterns)
+    }
+
+    /// Every key of the search has the same modulus, so there are only as
+    /// many keys as odd exponents.
+    fn key_space(&self) -> Option<f64> {
+        Some(((MAX_EXPONENT - MIN_EXPONENT) / 2 + 1) as f64)
+    }
+
+    fn fingerprint(&self, keypair: &RsaKeyPair) -> String {
+        blob_fingerprint(&keypair.blob())
+    }
+
+    fn write(
+        &self,
+        keypair: &RsaKeyPair,
+        dir: &Path,
+        name: &str,
+        options: &KeyFileOptions,
+    ) -> io::Result<PathBuf> {
+        write_openssh_key(
+            PrivateKey::from(keypair.to_ssh_keypair()?),
+            dir,
+            name,
+            options,
+        )
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        remove_openssh_key(path)
+    }
+}
+
+/// An RSA key pair being checked against patterns, with its fingerprint
+/// computed only if a pattern needs it.
+struct RsaCandidate<'a> {
+    keypair: &'a RsaKeyPair,
+    blob: Vec<u8>,
+    text: String,
+    fingerprint: OnceCell<String>,
+}
+
+impl<'a> RsaCandidate<'a> {
+    fn new(keypair: &'a RsaKeyPair) -> Self {
+        let blob = keypair.blob();
+        Self {
+            keypair,
+            text: STANDARD_NO_PAD.encode(&blob),
+            blob,
+            fingerprint: OnceCell::new(),
+        }
+    }
+
+    fn matches(&self, pattern: &Pattern) -> bool {
+        match pattern {
+            Pattern::Fingerprint(inner) => {
+                let fingerprint = self
+                    .fingerprint
+                    .get_or_init(|| blob_fingerprint(&self.blob));
+                inner.matches_str(fingerprint, &FINGERPRINT_LAYOUT)
+            }
+            Pattern::And(children) => children.iter().all(|c| self.matches(c)),
+            Pattern::Or(children) => children.iter().any(|c| self.matches(c)),
+            Pattern::Not(inner) => !self.matches(inner),
+            _ => pattern.matches_str(&self.text, self.keypair.modulus.layout()),
+        }
+    }
+}
+
+/// Generates `batch_size` key pairs with consecutive usable exponents from
+/// a random odd start.
+pub fn generate_rsa_batch(modulus: &'static RsaModulus, batch_size: usize) -> Vec<RsaKeyPair> {
+    let mut exponent = OsRng.gen_range(MIN_EXPONENT..=MAX_EXPONENT) | 1;
+    let mut keypairs = Vec::with_capacity(batch_size);
+    while keypairs.len() < batch_size {
+        if modulus.accepts(exponent) {
+            keypairs.push(RsaKeyPair { modulus, exponent });
+        }
+        exponent = match exponent {
+            MAX_EXPONENT => MIN_EXPONENT,
+            _ => exponent + 2,
+        };
+    }
+    keypairs
+}
+
+/// Encrypts and decrypts a random message and signs and verifies it, so a
+/// key whose exponents do not belong together is never written.
+fn check_round_trip(private_key: &RsaPrivateKey) -> io::Result<()> {
+    let public_key = private_key.to_public_key();
+    let mut message = [0u8; 32];
+    OsRng.fill_bytes(&mut message);
+
+    let ciphertext = public_key
+        .encrypt(&mut OsRng, Pkcs1v15Encrypt, &message)
+        .map_err(invalid_key)?;
+    let decrypted = private_key
+        .decrypt(Pkcs1v15Encrypt, &ciphertext)
+        .map_err(invalid_key)?;
+    if decrypted != message {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the RSA key does not decrypt what it encrypts",
+        ));
+    }
+
+    let signature = SigningKey::<Sha256>::new(private_key.clone())
+        .try_sign_with_rng(&mut OsRng, &message)
+        .map_err(invalid_key)?;
+    VerifyingKey::<Sha256>::new(public_key)
+        .verify(&message, &signature)
+        .map_err(invalid_key)
+}
+
+fn invalid_key(e: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn write_string(blob: &mut Vec<u8>, bytes: &[u8]) {
+    blob.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    blob.extend_from_slice(bytes);
+}
+
+/// An SSH mpint: a leading zero byte keeps a set top bit from reading as a
+/// sign.
+fn write_mpint(blob: &mut Vec<u8>, bytes: &[u8]) {
+    let padded = bytes.first().is_some_and(|&b| b & 0x80 != 0);
+    blob.extend_from_slice(&(bytes.len() as u32 + padded as u32).to_be_bytes());
+    if padded {
+        blob.push(0);
+    }
+    blob.extend_from_slice(bytes);
+}
+
+fn to_words(value: &BigUint) -> Vec<u32> {
+    let bytes = value.to_bytes_be();
+    let mut padded = vec![0; bytes.len().next_multiple_of(4) - bytes.len()];
+    padded.extend_from_slice(&bytes);
+    padded
+        .chunks_exact(4)
+        .map(|chunk| u32::from_be_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+fn remainder(words: &[u32], divisor: u32) -> u64 {
+    words
+        .iter()
+        .fold(0u64, |r, &word| ((r << 32) | word as u64) % divisor as u64)
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
@@ -0,0 +1,269 @@
+use std::fmt::Display;
+use std::sync::Arc;
+
+use crate::core::dictionary::Dictionary;
+
+/// Something that makes a key memorable, measured in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreComponent {
+    /// Length of the longest dictionary word
+    Word,
+    /// Length of the longest run of one repeated character
+    Repeat,
+    /// Length of the longest palindrome
+    Palindrome,
+}
+
+impl ScoreComponent {
+    fn name(&self) -> &'static str {
+        match self {
+            ScoreComponent::Word => "word",
+            ScoreComponent::Repeat => "repeat",
+            ScoreComponent::Palindrome => "palindrome",
+        }
+    }
+}
+
+/// Weighted score components, written as e.g. `word=2,repeat,palindrome`
+/// where a missing weight is 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreWeights(Vec<(ScoreComponent, f64)>);
+
+impl Display for ScoreWeights {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|(component, weight)| match weight {
+                1.0 => component.name().to_string(),
+                weight => format!("{}={}", component.name(), weight),
+            })
+            .collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+pub fn parse_score_weights(spec: &str) -> Result<ScoreWeights, String> {
+    let weights = spec
+        .split(',')
+        .map(|part| {
+            let (name, weight) = match part.split_once('=') {
+                Some((name, weight)) => {
+                    let weight = weight
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|w| w.is_finite() && *w > 0.0)
+                        .ok_or_else(|| format!("invalid weight '{}' for {}", weight, name))?;
+                    (name, weight)
+                }
+                None => (part, 1.0),
+            };
+            let component = match name.trim() {
+                "word" => ScoreComponent::Word,
+                "repeat" => ScoreComponent::Repeat,
+                "palindrome" => ScoreComponent::Palindrome,
+                other => {
+                    return Err(format!(
+                        "unknown score '{}', expected word, repeat or palindrome",
+                        other
+                    ));
+                }
+            };
+            Ok((component, weight))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(ScoreWeights(weights))
+}
+
+/// Rates how memorable a key's text is, higher is better.
+#[derive(Debug)]
+pub struct Scorer {
+    weights: ScoreWeights,
+    dictionary: Option<Arc<Dictionary>>,
+}
+
+impl Scorer {
+    /// Fails if the weights score words but there is no dictionary.
+    pub fn new(weights: ScoreWeights, dictionary: Option<Arc<Dictionary>>) -> Result<Self, String> {
+        let uses_words = weights
+            .0
+            .iter()
+            .any(|&(component, _)| component == ScoreComponent::Word);
+        if uses_words && dictionary.is_none() {
+            return Err("the word score needs a word list, pass it with --dictionary".to_string());
+        }
+        Ok(Self {
+            weights,
+            dictionary,
+        })
+    }
+
+    /// Scores the part of a key's text that is not fixed by the format.
+    pub fn score(&self, text: &[u8]) -> f64 {
+        self.weights
+            .0
+            .iter()
+            .map(|&(component, weight)| {
+                let length = match component {
+                    ScoreComponent::Word => self
+                        .dictionary
+                        .as_ref()
+                        .and_then(|dictionary| dictionary.longest_word_in(text))
+                        .map_or(0, str::len),
+                    ScoreComponent::Repeat => longest_run(text),
+                    ScoreComponent::Palindrome => longest_palindrome(text),
+                };
+                weight * length as f64
+            })
+            .sum()
+    }
+}
+
+fn longest_run(text: &[u8]) -> usize {
+    text.chunk_by(|a, b| a == b)
+        .map(<[u8]>::len)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Expands around every center, which is plenty fast for a few dozen
+/// characters.
+fn longest_palindrome(text: &[u8]) -> usize {
+    let n = text.len();
+    let mut longest = n.min(1);
+    for center in 0..2 * n {
+        let (mut left, mut right) = (center / 2, center / 2 + center % 2);
+        while right < n && text[left] == text[right] {
+            longest = longest.max(right - left + 1);
+            if left == 0 {
+                break;
+            }
+            left -= 1;
+            right += 1;
+        }
+    }
+    longest
+}
+
+/// Formats a score without decimals when it is a whole number.
+pub fn format_score(score: f64) -> String {
+    if score.fract() == 0.0 {
+        format!("{:.0}", score)
+    } else {
+        format!("{:.1}", score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::core::dictionary::WordPosition;
+    use crate::core::layout::OPENSSH_LAYOUT;
+
+    fn dictionary(words: &str) -> Arc<Dictionary> {
+        let path = std::env::temp_dir().join(format!(
+            "vanity-ssh-rs-score-words-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        fs::write(&path, words).unwrap();
+        let dictionary =
+            Dictionary::load(&path, 3, WordPosition::Head, false, &OPENSSH_LAYOUT).unwrap();
+        fs::remove_file(path).unwrap();
+        Arc::new(dictionary)
+    }
+
+    #[test]
+    fn weights_parse_with_a_default_of_one() {
+        let weights = parse_score_weights("word=2,repeat,palindrome=0.5").unwrap();
+        assert_eq!(
+            weights,
+            ScoreWeights(vec![
+                (ScoreComponent::Word, 2.0),
+                (ScoreComponent::Repeat, 1.0),
+                (ScoreComponent::Palindrome, 0.5),
+            ])
+        );
+        assert_eq!(weights.to_string(), "word=2,repeat,palindrome=0.5");
+        assert_eq!(
+            parse_score_weights(" repeat ").unwrap(),
+            ScoreWeights(vec![(ScoreComponent::Repeat, 1.0)])
+        );
+    }
+
+    #[test]
+    fn bad_weights_are_rejected() {
+        for weight in ["0", "-1", "x", "inf", "NaN", ""] {
+            assert_eq!(
+                parse_score_weights(&format!("repeat={}", weight)).unwrap_err(),
+                format!("invalid weight '{}' for repeat", weight)
+            );
+        }
+        for spec in ["vowel", "repeat,", "", "word;repeat"] {
+            assert!(
+                parse_score_weights(spec)
+                    .unwrap_err()
+                    .starts_with("unknown score"),
+                "{}",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn word_scores_need_a_dictionary() {
+        let weights = parse_score_weights("word,repeat").unwrap();
+        assert!(Scorer::new(weights.clone(), None).is_err());
+        assert!(Scorer::new(weights, Some(dictionary("cat"))).is_ok());
+        assert!(Scorer::new(parse_score_weights("repeat").unwrap(), None).is_ok());
+    }
+
+    #[test]
+    fn longest_runs() {
+        assert_eq!(longest_run(b""), 0);
+        assert_eq!(longest_run(b"a"), 1);
+        assert_eq!(longest_run(b"abc"), 1);
+        assert_eq!(longest_run(b"aabbbc"), 3);
+        assert_eq!(longest_run(b"abcccc"), 4);
+        assert_eq!(longest_run(b"aaAA"), 2);
+    }
+
+    #[test]
+    fn longest_palindromes() {
+        assert_eq!(longest_palindrome(b""), 0);
+        assert_eq!(longest_palindrome(b"a"), 1);
+        assert_eq!(longest_palindrome(b"ab"), 1);
+        assert_eq!(longest_palindrome(b"aa"), 2);
+        assert_eq!(longest_palindrome(b"abax"), 3);
+        assert_eq!(longest_palindrome(b"xabba"), 4);
+        assert_eq!(longest_palindrome(b"xyabcbaz"), 5);
+        assert_eq!(longest_palindrome(b"aaaa"), 4);
+        assert_eq!(longest_palindrome(b"abBA"), 1);
+    }
+
+    #[test]
+    fn scores_add_up_the_weighted_lengths() {
+        let scorer = Scorer::new(
+            parse_score_weights("word=2,repeat,palindrome=0.5").unwrap(),
+            Some(dictionary("cat\nhorse\ncats")),
+        )
+        .unwrap();
+        // "cats" is 4, "AAAA" is 4, "wxyxw" is 5.
+        assert_eq!(
+            scorer.score(b"zzcatsqAAAAwxyxw"),
+            2.0 * 4.0 + 4.0 + 0.5 * 5.0
+        );
+        // "cat" is 3, every run is 1 long, and so is every palindrome.
+        assert_eq!(scorer.score(b"bcatd"), 2.0 * 3.0 + 1.0 + 0.5);
+        assert_eq!(scorer.score(b""), 0.0);
+    }
+
+    #[test]
+    fn whole_scores_have_no_decimals() {
+        assert_eq!(format_score(12.0), "12");
+        assert_eq!(format_score(12.5), "12.5");
+    }
+}
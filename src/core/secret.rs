@@ -0,0 +1,170 @@
+use std::fmt;
+use std::io;
+
+use zeroize::Zeroize;
+
+/// Secret key material. It is wiped when dropped, never shows up in `Debug`
+/// output and cannot be cloned, so every copy that outlives a batch is one
+/// that was moved on purpose.
+///
+/// Hits can be moved into a page of their own that is locked into memory
+/// with [`SecretBytes::lock`], so they are never written to swap.
+pub struct SecretBytes<const N: usize> {
+    storage: Storage<N>,
+}
+
+enum Storage<const N: usize> {
+    Inline([u8; N]),
+    Locked(LockedPage<N>),
+}
+
+impl<const N: usize> SecretBytes<N> {
+    /// All zeros, to be filled in place with [`SecretBytes::expose_mut`]
+    /// rather than built from a copy that would be left behind.
+    pub fn zeroed() -> Self {
+        Self {
+            storage: Storage::Inline([0; N]),
+        }
+    }
+
+    pub fn expose(&self) -> &[u8; N] {
+        match &self.storage {
+            Storage::Inline(bytes) => bytes,
+            Storage::Locked(page) => page.bytes(),
+        }
+    }
+
+    pub fn expose_mut(&mut self) -> &mut [u8; N] {
+        match &mut self.storage {
+            Storage::Inline(bytes) => bytes,
+            Storage::Locked(page) => page.bytes_mut(),
+        }
+    }
+
+    /// Moves the bytes into memory locked with `mlock`. Leaves them where
+    /// they are if the page cannot be locked, e.g. over `RLIMIT_MEMLOCK`.
+    pub fn lock(&mut self) -> io::Result<()> {
+        let Storage::Inline(bytes) = &mut self.storage else {
+            return Ok(());
+        };
+        let mut page = LockedPage::new()?;
+        page.bytes_mut().copy_from_slice(bytes);
+        bytes.zeroize();
+        self.storage = Storage::Locked(page);
+        Ok(())
+    }
+
+    /// Moves the secret out, wiping the bytes left behind. Moving the value
+    /// itself would leave a copy wherever it was stored.
+    pub fn take(&mut self) -> Self {
+        match &mut self.storage {
+            Storage::Inline(bytes) => {
+                let mut taken = Self::zeroed();
+                taken.expose_mut().copy_from_slice(bytes);
+                bytes.zeroize();
+                taken
+            }
+            // Only the pointer moves.
+            Storage::Locked(_) => Self {
+                storage: std::mem::replace(&mut self.storage, Storage::Inline([0; N])),
+            },
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_locked(&self) -> bool {
+        matches!(self.storage, Storage::Locked(_))
+    }
+}
+
+impl<const N: usize> Drop for SecretBytes<N> {
+    fn drop(&mut self) {
+        // A locked page wipes itself.
+        if let Storage::Inline(bytes) = &mut self.storage {
+            bytes.zeroize();
+        }
+    }
+}
+
+impl<const N: usize> fmt::Debug for SecretBytes<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes<{}>(redacted)", N)
+    }
+}
+
+/// Checks that memory can be locked at all, so `--lock-memory` fails up
+/// front instead of quietly leaving hits unlocked.
+pub fn check_memory_lock() -> io::Result<()> {
+    LockedPage::<1>::new().map(drop)
+}
+
+/// A page-aligned allocation of whole pages, locked into RAM. Locks apply to
+/// whole pages and do not nest, so each secret gets pages of its own that
+/// unlocking cannot take away from another one.
+struct LockedPage<const N: usize> {
+    ptr: *mut u8,
+    layout: std::alloc::Layout,
+}
+
+// The page is owned like a `Box` and only reached through `&`/`&mut self`.
+unsafe impl<const N: usize> Send for LockedPage<N> {}
+unsafe impl<const N: usize> Sync for LockedPage<N> {}
+
+impl<const N: usize> LockedPage<N> {
+    #[cfg(unix)]
+    fn new() -> io::Result<Self> {
+        // SAFETY: sysconf has no preconditions.
+        let page_size = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+            size if size > 0 => size as usize,
+            _ => 4096,
+        };
+        let layout =
+            std::alloc::Layout::from_size_align(N.max(1).next_multiple_of(page_size), page_size)
+                .map_err(io::Error::other)?;
+        // SAFETY: the layout has a non-zero size.
+        let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            return Err(io::ErrorKind::OutOfMemory.into());
+        }
+        // SAFETY: `ptr` points to `layout.size()` bytes we own.
+        if unsafe { libc::mlock(ptr.cast(), layout.size()) } != 0 {
+            let error = io::Error::last_os_error();
+            // SAFETY: allocated above with the same layout.
+            unsafe { std::alloc::dealloc(ptr, layout) };
+            return Err(error);
+        }
+        Ok(Self { ptr, layout })
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "locking memory is only supported on Unix",
+        ))
+    }
+
+    fn bytes(&self) -> &[u8; N] {
+        // SAFETY: the allocation is at least `N` bytes, byte-aligned and
+        // initialized, and lives as long as `self`.
+        unsafe { &*self.ptr.cast::<[u8; N]>() }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8; N] {
+        // SAFETY: as in `bytes`, and `&mut self` makes the access unique.
+        unsafe { &mut *self.ptr.cast::<[u8; N]>() }
+    }
+}
+
+impl<const N: usize> Drop for LockedPage<N> {
+    fn drop(&mut self) {
+        self.bytes_mut().zeroize();
+        #[cfg(unix)]
+        // SAFETY: the range was locked in `new` and is still allocated.
+        unsafe {
+            libc::munlock(self.ptr.cast(), self.layout.size());
+        }
+        // SAFETY: allocated in `new` with the same layout.
+        unsafe { std::alloc::dealloc(self.ptr, self.layout) };
+    }
+}
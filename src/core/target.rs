@@ -0,0 +1,118 @@
+use clap::ValueEnum;
+use clap::builder::PossibleValue;
+
+use crate::core::keypair::KeyType;
+
+/// What the generated key is used for, which decides the
+/// [`crate::core::backend::KeyBackend`] of the search and what options it
+/// takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// OpenSSH key pair, patterns match the base64 public key
+    Ssh(KeyType),
+    /// Tor v3 onion service, patterns match the onion address
+    Onion,
+    /// WireGuard key pair, patterns match the base64 public key
+    WireGuard,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target::Ssh(KeyType::default())
+    }
+}
+
+/// `--target` only names the target. The key type of `ssh` comes from
+/// `--key-type` and is filled in with [`Target::with_key_type`].
+impl ValueEnum for Target {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Target::Ssh(KeyType::Ed25519),
+            Target::Onion,
+            Target::WireGuard,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self {
+            Target::Ssh(_) => PossibleValue::new("ssh")
+                .help("OpenSSH key pair, patterns match the base64 public key"),
+            Target::Onion => PossibleValue::new("onion")
+                .help("Tor v3 onion service, patterns match the onion address"),
+            Target::WireGuard => PossibleValue::new("wireguard")
+                .help("WireGuard key pair, patterns match the base64 public key"),
+        })
+    }
+}
+
+impl Target {
+    /// The target with `key_type`, or `None` if it has no such keys.
+    pub fn with_key_type(&self, key_type: KeyType) -> Option<Target> {
+        match self {
+            Target::Ssh(_) => Some(Target::Ssh(key_type)),
+            Target::Onion | Target::WireGuard => (key_type == KeyType::Ed25519).then_some(*self),
+        }
+    }
+
+    /// Whether the key can be written from its expanded secret alone, which
+    /// is what the point-walk strategy produces. OpenSSH ed25519 keys need
+    /// their seed, and ECDSA and RSA keys have searches of their own that
+    /// have no use for the strategy.
+    pub fn supports_expanded_secret(&self) -> bool {
+        match self {
+            Target::Ssh(_) => false,
+            Target::Onion | Target::WireGuard => true,
+        }
+    }
+
+    /// Whether hex patterns can be matched against the key bytes, which are
+    /// the 32 bytes of an ed25519 or X25519 public key.
+    pub fn supports_hex(&self) -> bool {
+        match self {
+            Target::Ssh(key_type) => *key_type == KeyType::Ed25519,
+            Target::Onion | Target::WireGuard => true,
+        }
+    }
+
+    /// Whether the written private key can be protected by a passphrase. Tor
+    /// and WireGuard have no format for encrypted keys.
+    pub fn supports_encryption(&self) -> bool {
+        match self {
+            Target::Ssh(_) => true,
+            Target::Onion | Target::WireGuard => false,
+        }
+    }
+
+    /// Whether the written key carries a comment. Tor and WireGuard key
+    /// files have none.
+    pub fn supports_comment(&self) -> bool {
+        match self {
+            Target::Ssh(_) => true,
+            Target::Onion | Target::WireGuard => false,
+        }
+    }
+
+    /// Whether `--install` knows where the key goes. Onion service keys live
+    /// wherever the torrc says, WireGuard keys in the interface config.
+    pub fn supports_install(&self) -> bool {
+        match self {
+            Target::Ssh(_) => true,
+            Target::Onion | Target::WireGuard => false,
+        }
+    }
+
+    /// Whether keys can be ranked with `--score`. RSA keys of a search only
+    /// differ in the exponent, a few characters of a text that is otherwise
+    /// the same for all of them.
+    pub fn supports_score(&self) -> bool {
+        *self != Target::Ssh(KeyType::Rsa)
+    }
+
+    /// Whether patterns may match the SHA256 fingerprint of the key.
+    pub fn supports_fingerprint(&self) -> bool {
+        match self {
+            Target::Ssh(_) => true,
+            Target::Onion | Target::WireGuard => false,
+        }
+    }
+}
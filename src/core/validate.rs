@@ -0,0 +1,282 @@
+use crate::core::fingerprint::FINGERPRINT_LAYOUT;
+use crate::core::layout::{CharSet, Layout};
+use crate::core::pattern::Pattern;
+use crate::core::regex_layout::regex_can_match;
+
+/// Explains why `pattern` can never match text distributed as `layout`.
+/// Returns `None` if it can match, or if that cannot be decided.
+pub fn unmatchable_reason(pattern: &Pattern, layout: &Layout) -> Option<String> {
+    match pattern {
+        Pattern::Suffix(suffix, _) => match layout.len().checked_sub(suffix.len()) {
+            Some(offset) => literal_reason(layout, offset, suffix),
+            None => invalid_character_reason(layout, suffix).or_else(|| {
+                Some(format!(
+                    "it is {} characters long, but the {} only has {}",
+                    suffix.len(),
+                    layout.name,
+                    layout.len()
+                ))
+            }),
+        },
+        Pattern::FoldedSuffix(suffix, folding, _) => {
+            let classes = folding.classes(suffix);
+            let offset = layout.len().saturating_sub(classes.len());
+            class_reason(layout, offset, suffix, &classes)
+        }
+        Pattern::Prefix(prefix, _) => literal_reason(layout, layout.variable_start(), prefix),
+        Pattern::At(offset, text, _) => literal_reason(layout, *offset, text),
+        Pattern::Contains(text) => {
+            if let Some(reason) = invalid_character_reason(layout, text) {
+                return Some(reason);
+            }
+            if text.len() > layout.len() {
+                return Some(format!(
+                    "it is {} characters long, but the {} only has {}",
+                    text.len(),
+                    layout.name,
+                    layout.len()
+                ));
+            }
+            if layout.contains_probability(text) == 0.0 {
+                return Some(format!(
+                    "it does not fit at any position of the {}, {}",
+                    layout.name,
+                    describe_layout(layout)
+                ));
+            }
+            None
+        }
+        // Any bytes can occur, and too many digits are rejected when parsing.
+        Pattern::Hex(..) => None,
+        Pattern::Regex(regex) => match regex_can_match(regex, layout) {
+            Some(false) => Some(format!(
+                "no {} matches it, {}",
+                layout.name,
+                describe_layout(layout)
+            )),
+            _ => None,
+        },
+        Pattern::Dictionary(dictionary) => (dictionary.probability(layout) == 0.0).then(|| {
+            format!(
+                "none of its {} words fits the {}, {}",
+                dictionary.len(),
+                layout.name,
+                describe_layout(layout)
+            )
+        }),
+        Pattern::Fingerprint(inner) => unmatchable_reason(inner, &FINGERPRINT_LAYOUT),
+        Pattern::And(children) => {
+            if let Some((child, reason)) = children
+                .iter()
+                .find_map(|child| Some((child, unmatchable_reason(child, layout)?)))
+            {
+                return Some(format!("its part '{}' can never match: {}", child, reason));
+            }
+            (pattern.estimate(layout) == Some((0.0, true))).then(|| {
+                format!(
+                    "its parts need different characters at the same position of the {}",
+                    layout.name
+                )
+            })
+        }
+        Pattern::Or(children) => {
+            let reasons = children
+                .iter()
+                .map(|child| unmatchable_reason(child, layout))
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!(
+                "none of its alternatives can match: {}",
+                reasons.join("; ")
+            ))
+        }
+        Pattern::Not(inner) => (inner.estimate(layout) == Some((1.0, true)))
+            .then(|| format!("'{}' matches every {}", inner, layout.name)),
+    }
+}
+
+fn literal_reason(layout: &Layout, offset: usize, text: &str) -> Option<String> {
+    let classes: Vec<CharSet> = text
+        .chars()
+        .map(|c| {
+            let mut class = CharSet::default();
+            if c.is_ascii() {
+                class.insert(c as u8);
+            }
+            class
+        })
+        .collect();
+    class_reason(layout, offset, text, &classes)
+}
+
+/// Like [`literal_reason`], for text whose characters may each be any of
+/// `classes`.
+fn class_reason(layout: &Layout, offset: usize, text: &str, classes: &[CharSet]) -> Option<String> {
+    let alphabet = layout.alphabet();
+    if let Some((c, _)) = text
+        .chars()
+        .zip(classes)
+        .find(|(_, class)| class.intersection(&alphabet).is_empty())
+    {
+        return Some(format!(
+            "'{}' never appears in a {}, which only uses {}",
+            c,
+            layout.name,
+            alphabet.describe()
+        ));
+    }
+    if offset + classes.len() > layout.len() {
+        return Some(format!(
+            "it ends at position {}, but the {} only has {} characters",
+            offset + classes.len(),
+            layout.name,
+            layout.len()
+        ));
+    }
+
+    for (i, class) in classes.iter().enumerate() {
+        let position = offset + i;
+        let allowed = layout.position(position);
+        if !allowed.intersection(class).is_empty() {
+            continue;
+        }
+        let needed = if class.len() == 1 {
+            format!("'{}'", class.describe())
+        } else {
+            format!("one of {}", class.describe())
+        };
+        return Some(if allowed.len() == 1 {
+            format!(
+                "character {} of the {} is always '{}', but the pattern needs {} there",
+                position,
+                layout.name,
+                allowed.describe(),
+                needed
+            )
+        } else {
+            format!(
+                "character {} of the {} can only be one of {}, but the pattern needs {} there",
+                position,
+                layout.name,
+                allowed.describe(),
+                needed
+            )
+        });
+    }
+
+    None
+}
+
+fn invalid_character_reason(layout: &Layout, text: &str) -> Option<String> {
+    let alphabet = layout.alphabet();
+    text.chars()
+        .find(|&c| !c.is_ascii() || !alphabet.contains(c as u8))
+        .map(|c| {
+            format!(
+                "'{}' never appears in a {}, which only uses {}",
+                c,
+                layout.name,
+                alphabet.describe()
+            )
+        })
+}
+
+fn describe_layout(layout: &Layout) -> String {
+    let fixed = layout.fixed_prefix();
+    if fixed.is_empty() {
+        format!(
+            "which is {} characters from {}",
+            layout.len(),
+            layout.alphabet().describe()
+        )
+    } else {
+        format!(
+            "which is {} characters starting with '{}'",
+            layout.len(),
+            fixed
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::layout::{Layout, OPENSSH_LAYOUT};
+    use crate::core::onion::ONION_LAYOUT;
+    use crate::core::pattern::{Pattern, PatternError};
+
+    /// Why `text` is rejected for `layout`, or `None` if it is accepted.
+    fn reason(text: &str, layout: &Layout) -> Option<String> {
+        match Pattern::new_for_layout(text.to_string(), layout) {
+            Ok(_) => None,
+            Err(PatternError::Unmatchable { reason, .. }) => Some(reason),
+            Err(e) => panic!("{} for {}", e, text),
+        }
+    }
+
+    #[test]
+    fn fixed_header_characters_are_checked() {
+        assert_eq!(
+            reason("at:0:B", &OPENSSH_LAYOUT).unwrap(),
+            "character 0 of the public key is always 'A', but the pattern needs 'B' there"
+        );
+        assert_eq!(
+            reason("prefix:abc", &OPENSSH_LAYOUT).unwrap(),
+            "character 25 of the public key can only be one of A-P, but the pattern needs 'a' there"
+        );
+        assert_eq!(reason("at:4:C3Nz", &OPENSSH_LAYOUT), None);
+        assert_eq!(reason("prefix:Pbc", &OPENSSH_LAYOUT), None);
+        assert_eq!(
+            reason("x", &ONION_LAYOUT).unwrap(),
+            "character 55 of the onion address is always 'd', but the pattern needs 'x' there"
+        );
+        assert_eq!(
+            reason("ci:X", &ONION_LAYOUT).unwrap(),
+            "character 55 of the onion address is always 'd', but the pattern needs one of Xx there"
+        );
+    }
+
+    #[test]
+    fn characters_outside_the_alphabet_are_rejected() {
+        for text in ["ab-c", "contains:a-b", "prefix:Ab-", "fp:ab-c"] {
+            let reason = reason(text, &OPENSSH_LAYOUT).unwrap();
+            assert!(reason.starts_with("'-' never appears in a"), "{}", reason);
+        }
+        assert!(
+            reason("ABC", &ONION_LAYOUT)
+                .unwrap()
+                .starts_with("'A' never appears in a onion address")
+        );
+    }
+
+    #[test]
+    fn texts_longer_than_the_layout_are_rejected() {
+        let long = "A".repeat(OPENSSH_LAYOUT.len() + 1);
+        assert_eq!(
+            reason(&long, &OPENSSH_LAYOUT).unwrap(),
+            "it is 69 characters long, but the public key only has 68"
+        );
+        assert_eq!(
+            reason("at:66:abc", &OPENSSH_LAYOUT).unwrap(),
+            "it ends at position 69, but the public key only has 68 characters"
+        );
+        // Every position of the address can hold `a`, but no run of 57 fits.
+        assert!(reason(&format!("contains:{}", "a".repeat(57)), &ONION_LAYOUT).is_some());
+    }
+
+    #[test]
+    fn expressions_that_can_never_match_are_rejected() {
+        assert_eq!(
+            reason("prefix:Ab & prefix:Bb", &OPENSSH_LAYOUT).unwrap(),
+            "its parts need different characters at the same position of the public key"
+        );
+        assert!(
+            reason("prefix:abc | at:0:B", &OPENSSH_LAYOUT)
+                .unwrap()
+                .starts_with("none of its alternatives can match")
+        );
+        assert_eq!(reason("prefix:abc | xyz", &OPENSSH_LAYOUT), None);
+        assert_eq!(
+            reason("!/A/", &OPENSSH_LAYOUT).unwrap(),
+            "'Regex: A' matches every public key"
+        );
+    }
+}
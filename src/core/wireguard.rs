@@ -0,0 +1,126 @@
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use curve25519_dalek::edwards::EdwardsPoint;
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use zeroize::Zeroizing;
+
+use crate::core::backend::KeyBackend;
+use crate::core::file_io::{KeyFileOptions, create_unique_dir, write_secret_file};
+use crate::core::keypair::{Curve25519KeyPair, PublicPoint, Strategy};
+use crate::core::layout::{BASE64_ALPHABET, BlobByte, Layout};
+use crate::core::pattern::Pattern;
+use crate::core::pattern_set::PatternSet;
+
+/// Layout of a WireGuard public key: 32 random bytes in base64, without the
+/// `=` padding every key ends with.
+pub static WIREGUARD_LAYOUT: LazyLock<Layout> = LazyLock::new(|| {
+    let blob = [BlobByte::Random; 32];
+    Layout::encoded("public key", &blob, BASE64_ALPHABET)
+});
+
+/// X25519 public keys are the Montgomery form of the ed25519 points, which
+/// the clamped ed25519 scalar is also a valid X25519 private key for.
+impl PublicPoint for MontgomeryPoint {
+    fn encode_batch(points: &[EdwardsPoint]) -> Vec<Self> {
+        EdwardsPoint::to_montgomery_batch(points)
+    }
+}
+
+pub type WireGuardKeyPair = Curve25519KeyPair<MontgomeryPoint>;
+
+/// WireGuard key pairs. Patterns match the base64 public key, and the key
+/// is written as a directory with `privatekey`, `publickey` and an
+/// `[Interface]` snippet.
+#[derive(Debug, Clone, Copy)]
+pub struct WireGuardBackend {
+    pub strategy: Strategy,
+}
+
+impl KeyBackend for WireGuardBackend {
+    type KeyPair = WireGuardKeyPair;
+
+    fn layout(&self) -> &'static Layout {
+        &WIREGUARD_LAYOUT
+    }
+
+    fn generate_batch(&self, batch_size: usize) -> Vec<WireGuardKeyPair> {
+        self.strategy.generate_batch(batch_size)
+    }
+
+    fn text(&self, keypair: &WireGuardKeyPair) -> String {
+        STANDARD_NO_PAD.encode(keypair.public_key.as_bytes())
+    }
+
+    fn find_match<'a>(
+        &self,
+        keypair: &WireGuardKeyPair,
+        patterns: &'a PatternSet,
+    ) -> Option<&'a Pattern> {
+        let public_key = keypair.public_key.as_bytes();
+        let text = self.text(keypair);
+        patterns.find_match(
+            |i| text.as_bytes().iter().rev().nth(i).copied(),
+            |p| p.matches_key(public_key, &text, &WIREGUARD_LAYOUT),
+        )
+    }
+
+    fn fingerprint(&self, keypair: &WireGuardKeyPair) -> String {
+        self.text(keypair)
+    }
+
+    fn write(
+        &self,
+        keypair: &WireGuardKeyPair,
+        dir: &Path,
+        name: &str,
+        _options: &KeyFileOptions,
+    ) -> io::Result<PathBuf> {
+        save_wireguard_keys(keypair, dir, name)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+
+    fn saved_message(&self, keypair: &WireGuardKeyPair, path: &Path) -> String {
+        format!(
+            "WireGuard key {} saved to '{}/'",
+            STANDARD.encode(keypair.public_key.as_bytes()),
+            path.display()
+        )
+    }
+}
+
+/// Writes the directory `dir/{name}` with `privatekey` and `publickey` as
+/// `wg genkey` and `wg pubkey` print them, and `interface.conf` with an
+/// `[Interface]` section to paste into the config of the interface.
+fn save_wireguard_keys(keypair: &WireGuardKeyPair, dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let key_dir = create_unique_dir(dir, name)?;
+
+    // The private key is the clamped scalar, which WireGuard clamps again
+    // to the same value.
+    let expanded = keypair.secret_key.expanded();
+    let mut private_key = Zeroizing::new(String::with_capacity(45));
+    STANDARD.encode_string(&expanded.expose()[..32], &mut private_key);
+    let public_key = STANDARD.encode(keypair.public_key.as_bytes());
+
+    let mut private_file = Zeroizing::new(String::with_capacity(45));
+    private_file.push_str(&private_key);
+    private_file.push('\n');
+    write_secret_file(&key_dir.join("privatekey"), private_file.as_bytes())?;
+    fs::write(key_dir.join("publickey"), format!("{}\n", public_key))?;
+
+    let mut interface = Zeroizing::new(String::with_capacity(128));
+    interface.push_str("[Interface]\nPrivateKey = ");
+    interface.push_str(&private_key);
+    interface.push_str("\n# PublicKey = ");
+    interface.push_str(&public_key);
+    interface.push('\n');
+    write_secret_file(&key_dir.join("interface.conf"), interface.as_bytes())?;
+
+    Ok(key_dir)
+}
@@ -1,8 +1,9 @@
-use clap::Parser;
-use num_cpus;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use clap::{Parser, ValueEnum};
+use std::env;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
 use std::sync::mpsc::channel;
+use std::sync::{Arc, RwLock};
 use std::time::Instant;
 
 mod cli;
@@ -11,20 +12,146 @@ mod manager;
 mod worker;
 
 use cli::Args;
-use core::pattern::Pattern;
+use cli::interrupt::install_interrupt_handler;
+use cli::passphrase::{passphrase_requested, read_passphrase};
+use cli::patterns_file::read_patterns_file;
+use core::backend::KeyBackend;
+use core::dictionary::Dictionary;
+use core::ecdsa::EcdsaBackend;
+use core::file_io::{KeyEncryption, KeyWriter};
+use core::install::{DEFAULT_INSTALL_DIR, SSH_CONFIG, check_install_target, expand_home};
+use core::keypair::{Ed25519Backend, KeyType, Strategy};
+use core::layout::Layout;
+use core::onion::OnionBackend;
+use core::pattern::{Pattern, PatternError};
+use core::pattern_set::PatternSet;
+use core::rsa::RsaBackend;
+use core::score::Scorer;
+use core::secret::check_memory_lock;
+use core::target::Target;
+use core::wireguard::WireGuardBackend;
+use manager::leaderboard::Leaderboard;
+use manager::limits::SearchLimits;
 use manager::manager::run_manager;
-use worker::spawn_worker_threads;
+use worker::{new_score_threshold, spawn_worker_threads};
 
 fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    args.target = match args.target.with_key_type(args.key_type) {
+        Some(target) => target,
+        None => {
+            eprintln!(
+                "--key-type {} cannot be used with --target {}",
+                args.key_type.to_possible_value().unwrap().get_name(),
+                args.target.to_possible_value().unwrap().get_name()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    match args.target {
+        Target::Ssh(KeyType::Ed25519) => search(
+            Ed25519Backend {
+                strategy: args.strategy,
+            },
+            args,
+        ),
+        Target::Ssh(KeyType::Rsa) => search(rsa_backend(&args), args),
+        Target::Ssh(key_type) => search(
+            EcdsaBackend {
+                curve: key_type.curve().unwrap(),
+            },
+            args,
+        ),
+        Target::Onion => search(
+            OnionBackend {
+                strategy: args.strategy,
+            },
+            args,
+        ),
+        Target::WireGuard => search(
+            WireGuardBackend {
+                strategy: args.strategy,
+            },
+            args,
+        ),
+    }
+}
+
+/// Generates the modulus of an RSA search. The layout of RSA keys depends
+/// on it, so it has to exist before patterns are checked.
+fn rsa_backend(args: &Args) -> RsaBackend {
+    println!(
+        "Generating a {}-bit RSA modulus, the search varies its public exponent.",
+        args.rsa_bits
+    );
+    match RsaBackend::generate(args.rsa_bits as usize) {
+        Ok(backend) => backend,
+        Err(e) => {
+            eprintln!("Failed to generate the RSA modulus: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Checks the patterns and options against `backend` and runs the search.
+fn search<B: KeyBackend>(backend: B, mut args: Args) {
     let n_threads = args.threads.unwrap_or_else(num_cpus::get);
+    let mut pattern_strings = args.patterns.clone();
+    if let Some(path) = &args.patterns_file {
+        match read_patterns_file(path) {
+            Ok(file_patterns) => pattern_strings.extend(file_patterns),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if pattern_strings.is_empty() && args.dictionary.is_none() && args.score.is_none() {
+        eprintln!(
+            "No patterns given, pass them as arguments, with --patterns-file, --dictionary or --score"
+        );
+        std::process::exit(1);
+    }
+
+    if args.score.is_some() && !args.target.supports_score() {
+        eprintln!(
+            "--score cannot be used with --key-type {}",
+            args.key_type.to_possible_value().unwrap().get_name()
+        );
+        std::process::exit(1);
+    }
 
-    let patterns: Result<Vec<Pattern>, regex::Error> = args
-        .patterns
-        .iter()
-        .map(|p| Pattern::new(p.clone()))
+    let mut patterns: Result<Vec<Pattern>, PatternError> = pattern_strings
+        .into_iter()
+        .map(|p| Pattern::new_for_layout(p, backend.layout()))
         .collect();
 
+    // In scoring mode the dictionary provides the words for the word score
+    // instead of being a pattern of its own.
+    let dictionary = load_dictionary(&args, backend.layout());
+    let (dictionary, scorer) = match &args.score {
+        Some(weights) => match Scorer::new(weights.clone(), dictionary.map(Arc::new)) {
+            Ok(scorer) => (None, Some(Arc::new(scorer))),
+            Err(e) => {
+                eprintln!("Invalid score: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => (dictionary, None),
+    };
+
+    if let (Ok(patterns), Some(dictionary)) = (&mut patterns, dictionary) {
+        match Pattern::from_dictionary(dictionary, backend.layout()) {
+            Ok(pattern) => patterns.push(pattern),
+            Err(e) => {
+                eprintln!("Invalid pattern: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     let patterns = match patterns {
         Ok(patterns) => patterns,
         Err(e) => {
@@ -33,20 +160,232 @@ fn main() {
         }
     };
 
-    find_matching_key(patterns, n_threads, args);
+    if let Some(key_space) = backend.key_space()
+        && let Some(pattern) = patterns.iter().find(|p| {
+            p.probability(backend.layout())
+                .is_some_and(|probability| probability * key_space < 1.0)
+        })
+    {
+        eprintln!(
+            "Pattern '{}' is too rare for --key-type {}: one key in {:.0} matches it, but the search can only try {:.0} keys",
+            pattern,
+            args.key_type.to_possible_value().unwrap().get_name(),
+            1.0 / pattern.probability(backend.layout()).unwrap(),
+            key_space
+        );
+        std::process::exit(1);
+    }
+
+    if !args.target.supports_fingerprint() && patterns.iter().any(Pattern::reads_fingerprint) {
+        eprintln!(
+            "Fingerprint patterns cannot be used with --target {}",
+            args.target.to_possible_value().unwrap().get_name()
+        );
+        std::process::exit(1);
+    }
+
+    if !args.target.supports_hex() && patterns.iter().any(Pattern::reads_key_bytes) {
+        eprintln!(
+            "Hex patterns cannot be used with --key-type {}",
+            args.key_type.to_possible_value().unwrap().get_name()
+        );
+        std::process::exit(1);
+    }
+
+    if args.strategy == Strategy::PointWalk && !args.target.supports_expanded_secret() {
+        eprintln!(
+            "--strategy point-walk cannot be used with --key-type {}: only onion and wireguard keys can be written from a walked scalar",
+            args.key_type.to_possible_value().unwrap().get_name()
+        );
+        std::process::exit(1);
+    }
+
+    if args.install.is_some() && !args.target.supports_install() {
+        eprintln!(
+            "--install cannot be used with --target {}",
+            args.target.to_possible_value().unwrap().get_name()
+        );
+        std::process::exit(1);
+    }
+    if let Some(path) = &args.install {
+        let path = match path.as_os_str().is_empty() {
+            true => Path::new(DEFAULT_INSTALL_DIR).join(args.key_type.default_file_name()),
+            false => path.clone(),
+        };
+        let path = match expand_home(&path) {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("Invalid --install path: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let config = match &args.ssh_host {
+            Some(_) => match expand_home(Path::new(SSH_CONFIG)) {
+                Ok(config) => Some(config),
+                Err(e) => {
+                    eprintln!("Cannot find the SSH config: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            None => None,
+        };
+        if let Err(e) = check_install_target(&path, config.as_deref()) {
+            eprintln!("Cannot install the key: {}", e);
+            std::process::exit(1);
+        }
+        args.install = Some(path);
+    }
+
+    if args.lock_memory
+        && let Err(e) = check_memory_lock()
+    {
+        eprintln!("--lock-memory cannot lock memory: {}", e);
+        std::process::exit(1);
+    }
+
+    let limits = match SearchLimits::from_args(&args, &patterns) {
+        Ok(limits) => limits,
+        Err(e) => {
+            eprintln!("Invalid stop condition: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let writer = key_writer(&args, backend.clone());
+
+    find_matching_key(backend, patterns, scorer, writer, limits, n_threads, args);
+}
+
+/// Sets up where and how keys are saved, exiting if the options cannot be
+/// used.
+fn key_writer<B: KeyBackend>(args: &Args, backend: B) -> KeyWriter<B> {
+    if args.comment.is_some() && !args.target.supports_comment() {
+        eprintln!(
+            "Keys for --target {} have no comment",
+            args.target.to_possible_value().unwrap().get_name()
+        );
+        std::process::exit(1);
+    }
+    let comment = args.comment.clone().unwrap_or_else(default_comment);
+
+    match KeyWriter::new(
+        backend,
+        args.out_dir.clone(),
+        args.name_template.clone(),
+        comment,
+        key_encryption(args),
+    ) {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!("Invalid --name-template: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `user@host`, the comment `ssh-keygen` gives new keys.
+fn default_comment() -> String {
+    let host = gethostname::gethostname().to_string_lossy().into_owned();
+    match env::var("USER").or_else(|_| env::var("USERNAME")) {
+        Ok(user) if !user.is_empty() => format!("{}@{}", user, host),
+        _ => host,
+    }
 }
 
-fn find_matching_key(patterns: Vec<Pattern>, n_threads: usize, args: Args) {
-    let patterns = Arc::new(patterns);
+/// Reads the passphrase for private keys, if one was asked for, exiting if
+/// it cannot be used.
+fn key_encryption(args: &Args) -> Option<KeyEncryption> {
+    if passphrase_requested(args) && !args.target.supports_encryption() {
+        eprintln!(
+            "Private keys for --target {} cannot be encrypted",
+            args.target.to_possible_value().unwrap().get_name()
+        );
+        std::process::exit(1);
+    }
+    let passphrase = match read_passphrase(args) {
+        Ok(passphrase) => passphrase?,
+        Err(e) => {
+            eprintln!("Failed to read the passphrase: {}", e);
+            std::process::exit(1);
+        }
+    };
+    Some(KeyEncryption {
+        passphrase,
+        kdf_rounds: args.kdf_rounds,
+    })
+}
+
+/// Reads `--dictionary`, exiting if it cannot be used.
+fn load_dictionary(args: &Args, layout: &Layout) -> Option<Dictionary> {
+    let path = args.dictionary.as_ref()?;
+    let dictionary = match Dictionary::load(
+        path,
+        args.min_len,
+        args.dictionary_position,
+        args.dictionary_ignore_case,
+        layout,
+    ) {
+        Ok(dictionary) => dictionary,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    if dictionary.is_empty() {
+        eprintln!(
+            "{} has no words of at least {} letters that can appear in a {}",
+            path.display(),
+            args.min_len,
+            layout.name
+        );
+        std::process::exit(1);
+    }
+    Some(dictionary)
+}
+
+fn find_matching_key<B: KeyBackend>(
+    backend: B,
+    patterns: Vec<Pattern>,
+    scorer: Option<Arc<Scorer>>,
+    writer: KeyWriter<B>,
+    limits: SearchLimits,
+    n_threads: usize,
+    args: Args,
+) {
+    let live_patterns = Arc::new(RwLock::new(Arc::new(PatternSet::new(patterns.clone()))));
     let start = Instant::now();
     let (tx, rx) = channel();
     let stop_flag = Arc::new(AtomicBool::new(false));
+    let score_threshold = new_score_threshold();
+    let leaderboard = scorer
+        .is_some()
+        .then(|| Leaderboard::new(args.top as usize, Arc::clone(&score_threshold)));
+
+    if let Err(e) = install_interrupt_handler(Arc::clone(&stop_flag)) {
+        eprintln!("Failed to install Ctrl-C handler: {}", e);
+    }
 
     println!("Using {} threads for parallel processing.", n_threads);
 
-    let _handles =
-        spawn_worker_threads(n_threads, Arc::clone(&patterns), tx, Arc::clone(&stop_flag));
+    let _handles = spawn_worker_threads(
+        n_threads,
+        Arc::clone(&live_patterns),
+        backend,
+        scorer.map(|scorer| (scorer, score_threshold)),
+        args.lock_memory,
+        tx,
+        Arc::clone(&stop_flag),
+    );
 
-    run_manager(rx, start, &patterns, &args);
-    stop_flag.store(true, Ordering::Relaxed);
+    run_manager(
+        rx,
+        start,
+        &patterns,
+        &live_patterns,
+        leaderboard,
+        writer,
+        &limits,
+        &stop_flag,
+        &args,
+    );
 }
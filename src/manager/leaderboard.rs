@@ -0,0 +1,170 @@
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+
+use crate::core::backend::KeyBackend;
+use crate::core::file_io::KeyWriter;
+use crate::core::score::format_score;
+use crate::worker::ScoreThreshold;
+use crate::worker::message::ScoredKey;
+
+pub struct LeaderboardEntry {
+    pub score: f64,
+    pub text: String,
+    pub path: PathBuf,
+}
+
+/// The best keys of a scoring search, kept on disk. A key that makes it into
+/// the top `capacity` pushes out the worst one, whose files are removed.
+pub struct Leaderboard {
+    capacity: usize,
+    /// Best first.
+    entries: Vec<LeaderboardEntry>,
+    threshold: ScoreThreshold,
+}
+
+impl Leaderboard {
+    pub fn new(capacity: usize, threshold: ScoreThreshold) -> Self {
+        Self {
+            capacity,
+            entries: Vec::new(),
+            threshold,
+        }
+    }
+
+    pub fn entries(&self) -> &[LeaderboardEntry] {
+        &self.entries
+    }
+
+    pub fn best(&self) -> Option<&LeaderboardEntry> {
+        self.entries.first()
+    }
+
+    /// Saves the key with `writer` if it is among the best so far and returns
+    /// its entry.
+    pub fn offer<B: KeyBackend>(
+        &mut self,
+        scored_key: ScoredKey<B::KeyPair>,
+        writer: &mut KeyWriter<B>,
+    ) -> std::io::Result<Option<&LeaderboardEntry>> {
+        // Workers may send keys scored against an older threshold.
+        let is_full = self.entries.len() >= self.capacity;
+        if is_full
+            && self
+                .entries
+                .last()
+                .is_some_and(|worst| scored_key.score <= worst.score)
+        {
+            return Ok(None);
+        }
+
+        let pattern_name = format!("score{}", format_score(scored_key.score));
+        let path = writer.save(&scored_key.key_pair, &pattern_name)?;
+
+        let rank = self
+            .entries
+            .iter()
+            .position(|entry| scored_key.score > entry.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(
+            rank,
+            LeaderboardEntry {
+                score: scored_key.score,
+                text: scored_key.text,
+                path,
+            },
+        );
+
+        if self.entries.len() > self.capacity
+            && let Some(worst) = self.entries.pop()
+        {
+            writer.remove(&worst.path)?;
+        }
+
+        if self.entries.len() >= self.capacity
+            && let Some(worst) = self.entries.last()
+        {
+            self.threshold
+                .store(worst.score.to_bits(), Ordering::Relaxed);
+        }
+
+        Ok(self.entries.get(rank))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::core::keypair::{Ed25519Backend, Strategy};
+    use crate::worker::new_score_threshold;
+
+    fn offer(
+        leaderboard: &mut Leaderboard,
+        writer: &mut KeyWriter<Ed25519Backend>,
+        score: f64,
+    ) -> Option<PathBuf> {
+        let key_pair = writer.backend().generate_batch(1).remove(0);
+        let text = writer.backend().text(&key_pair);
+        let scored_key = ScoredKey {
+            key_pair,
+            score,
+            text,
+        };
+        let entry = leaderboard.offer(scored_key, writer).unwrap()?;
+        assert_eq!(entry.score, score);
+        Some(entry.path.clone())
+    }
+
+    fn scores(leaderboard: &Leaderboard) -> Vec<f64> {
+        leaderboard.entries().iter().map(|e| e.score).collect()
+    }
+
+    #[test]
+    fn keeps_the_best_keys_and_removes_the_rest() {
+        let dir =
+            std::env::temp_dir().join(format!("vanity-ssh-rs-leaderboard-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let backend = Ed25519Backend {
+            strategy: Strategy::Seed,
+        };
+        let mut writer = KeyWriter::new(
+            backend,
+            dir.clone(),
+            "{pattern}".to_string(),
+            "test@vanity".to_string(),
+            None,
+        )
+        .unwrap();
+        let threshold = new_score_threshold();
+        let mut leaderboard = Leaderboard::new(2, threshold.clone());
+        let threshold = || f64::from_bits(threshold.load(Ordering::Relaxed));
+
+        // Until the board is full every key makes it in.
+        let five = offer(&mut leaderboard, &mut writer, 5.0).unwrap();
+        assert_eq!(threshold(), f64::NEG_INFINITY);
+        let three = offer(&mut leaderboard, &mut writer, 3.0).unwrap();
+        assert_eq!(scores(&leaderboard), [5.0, 3.0]);
+        assert_eq!(threshold(), 3.0);
+
+        // A key no better than the worst is not saved.
+        assert!(offer(&mut leaderboard, &mut writer, 3.0).is_none());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+
+        // A better one pushes out the worst and its files.
+        let four = offer(&mut leaderboard, &mut writer, 4.0).unwrap();
+        assert_eq!(scores(&leaderboard), [5.0, 4.0]);
+        assert!(!three.exists());
+        assert!(four.exists() && five.exists());
+        assert_eq!(threshold(), 4.0);
+
+        let nine = offer(&mut leaderboard, &mut writer, 9.0).unwrap();
+        assert_eq!(scores(&leaderboard), [9.0, 5.0]);
+        assert_eq!(leaderboard.best().unwrap().path, nine);
+        assert!(!four.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+        assert_eq!(threshold(), 5.0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use crate::cli::Args;
+use crate::core::keypair::KeyType;
+use crate::core::pattern::Pattern;
+use crate::core::target::Target;
+
+pub struct SearchLimits {
+    quotas: Vec<(Pattern, usize)>,
+    pub max_keys: Option<usize>,
+    pub timeout: Option<Duration>,
+    pub max_attempts: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    QuotasReached,
+    MaxKeys,
+    Timeout,
+    MaxAttempts,
+    WorkersStopped,
+    Interrupted,
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            StopReason::QuotasReached => "all patterns reached their key count",
+            StopReason::MaxKeys => "maximum number of keys found",
+            StopReason::Timeout => "timeout reached",
+            StopReason::MaxAttempts => "maximum number of attempts reached",
+            StopReason::WorkersStopped => "all worker threads have stopped",
+            StopReason::Interrupted => "interrupted",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl SearchLimits {
+    pub fn from_args(args: &Args, patterns: &[Pattern]) -> Result<Self, String> {
+        let quotas = match args.count.as_slice() {
+            [] => Vec::new(),
+            [count] => patterns.iter().map(|p| (p.clone(), *count)).collect(),
+            counts if counts.len() == patterns.len() => patterns
+                .iter()
+                .cloned()
+                .zip(counts.iter().copied())
+                .collect(),
+            counts => {
+                return Err(format!(
+                    "--count takes one value or one value per pattern, got {} values for {} patterns",
+                    counts.len(),
+                    patterns.len()
+                ));
+            }
+        };
+
+        if quotas.iter().any(|&(_, count)| count == 0) {
+            return Err("--count must be at least 1".to_string());
+        }
+
+        // All RSA keys of a run share one modulus, and anyone holding two of
+        // them, or even one private key, could derive the others.
+        let single_key = args.target == Target::Ssh(KeyType::Rsa);
+        if single_key
+            && (args.max_keys.is_some_and(|max_keys| max_keys > 1)
+                || quotas.iter().any(|&(_, count)| count > 1))
+        {
+            return Err(
+                "--key-type rsa saves a single key per run, since its keys share one modulus"
+                    .to_string(),
+            );
+        }
+
+        // `--stop-after-match` is shorthand for a global limit of one key, and
+        // `--install` has only one place to put a key.
+        let stop_after_match = args.stop_after_match || args.install.is_some() || single_key;
+        let max_keys = match (args.max_keys, stop_after_match) {
+            (Some(max_keys), _) => Some(max_keys),
+            (None, true) => Some(1),
+            (None, false) => None,
+        };
+
+        Ok(Self {
+            quotas,
+            max_keys,
+            timeout: args.timeout,
+            max_attempts: args.max_attempts,
+        })
+    }
+
+    pub fn quota(&self, pattern: &Pattern) -> Option<usize> {
+        self.quotas
+            .iter()
+            .find(|(p, _)| p == pattern)
+            .map(|&(_, count)| count)
+    }
+
+    pub fn is_quota_reached(&self, pattern: &Pattern, n_hits: usize) -> bool {
+        self.quota(pattern).is_some_and(|quota| n_hits >= quota)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn limits(key_type: KeyType, options: &[&str]) -> Result<SearchLimits, String> {
+        let mut args = Args::parse_from(["vanity-ssh-rs", "abc"].iter().chain(options));
+        args.target = Target::Ssh(key_type);
+        let patterns = vec![Pattern::new("abc".to_string()).unwrap()];
+        SearchLimits::from_args(&args, &patterns)
+    }
+
+    fn patterns(texts: &[&str]) -> Vec<Pattern> {
+        texts
+            .iter()
+            .map(|text| Pattern::new(text.to_string()).unwrap())
+            .collect()
+    }
+
+    /// Limits for ed25519 keys and the patterns `abc`, `xyz` and `dev`.
+    fn limits_for_three(options: &[&str]) -> Result<SearchLimits, String> {
+        let args = Args::parse_from(["vanity-ssh-rs", "abc", "xyz", "dev"].iter().chain(options));
+        SearchLimits::from_args(&args, &patterns(&["abc", "xyz", "dev"]))
+    }
+
+    fn quotas(limits: &SearchLimits) -> Vec<Option<usize>> {
+        patterns(&["abc", "xyz", "dev"])
+            .iter()
+            .map(|pattern| limits.quota(pattern))
+            .collect()
+    }
+
+    #[test]
+    fn one_count_applies_to_every_pattern() {
+        assert_eq!(quotas(&limits_for_three(&[]).unwrap()), [None, None, None]);
+        assert_eq!(
+            quotas(&limits_for_three(&["-c", "2"]).unwrap()),
+            [Some(2), Some(2), Some(2)]
+        );
+    }
+
+    #[test]
+    fn counts_apply_to_the_patterns_in_order() {
+        assert_eq!(
+            quotas(&limits_for_three(&["--count", "1,2,3"]).unwrap()),
+            [Some(1), Some(2), Some(3)]
+        );
+        assert_eq!(
+            quotas(&limits_for_three(&["-c", "3", "-c", "1", "-c", "2"]).unwrap()),
+            [Some(3), Some(1), Some(2)]
+        );
+    }
+
+    #[test]
+    fn counts_must_match_the_patterns() {
+        for counts in ["1,2", "1,2,3,4"] {
+            assert_eq!(
+                limits_for_three(&["-c", counts]).err().unwrap(),
+                format!(
+                    "--count takes one value or one value per pattern, got {} values for 3 patterns",
+                    counts.split(',').count()
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn zero_counts_are_rejected() {
+        for counts in ["0", "1,0,2"] {
+            assert_eq!(
+                limits_for_three(&["-c", counts]).err().unwrap(),
+                "--count must be at least 1"
+            );
+        }
+    }
+
+    #[test]
+    fn quota_is_reached_at_the_count() {
+        let limits = limits_for_three(&["-c", "2,1,3"]).unwrap();
+        let [abc, xyz, _] = <[Pattern; 3]>::try_from(patterns(&["abc", "xyz", "dev"])).unwrap();
+        assert!(!limits.is_quota_reached(&abc, 0));
+        assert!(!limits.is_quota_reached(&abc, 1));
+        assert!(limits.is_quota_reached(&abc, 2));
+        assert!(limits.is_quota_reached(&abc, 3));
+        assert!(limits.is_quota_reached(&xyz, 1));
+
+        let other = Pattern::new("other".to_string()).unwrap();
+        assert!(!limits.is_quota_reached(&other, 100));
+        let unlimited = limits_for_three(&[]).unwrap();
+        assert!(!unlimited.is_quota_reached(&abc, 100));
+    }
+
+    #[test]
+    fn global_limits() {
+        let limits = limits_for_three(&["--max-keys", "3", "--max-attempts", "1000"]).unwrap();
+        assert_eq!(limits.max_keys, Some(3));
+        assert_eq!(limits.max_attempts, Some(1000));
+        assert_eq!(limits.timeout, None);
+
+        let limits = limits_for_three(&["--timeout", "1h 30m"]).unwrap();
+        assert_eq!(limits.timeout, Some(Duration::from_secs(90 * 60)));
+        assert_eq!(limits.max_keys, None);
+
+        assert_eq!(limits_for_three(&["-s"]).unwrap().max_keys, Some(1));
+        assert_eq!(limits_for_three(&["--install"]).unwrap().max_keys, Some(1));
+        assert_eq!(
+            limits_for_three(&["-s", "--max-keys", "5"])
+                .unwrap()
+                .max_keys,
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn rsa_runs_stop_after_one_key() {
+        assert_eq!(limits(KeyType::Rsa, &[]).unwrap().max_keys, Some(1));
+        assert_eq!(
+            limits(KeyType::Rsa, &["-c", "1"]).unwrap().max_keys,
+            Some(1)
+        );
+        assert_eq!(limits(KeyType::Ed25519, &[]).unwrap().max_keys, None);
+    }
+
+    #[test]
+    fn rsa_runs_reject_a_second_key() {
+        assert!(limits(KeyType::Rsa, &["--max-keys", "2"]).is_err());
+        assert!(limits(KeyType::Rsa, &["-c", "2"]).is_err());
+        assert!(limits(KeyType::Ed25519, &["--max-keys", "2"]).is_ok());
+    }
+}
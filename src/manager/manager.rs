@@ -1,29 +1,66 @@
 use indicatif::{ProgressBar, ProgressStyle};
 use num_format::{Locale, ToFormattedString};
 use std::collections::{HashMap, VecDeque};
-use std::sync::mpsc::Receiver;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 use std::time::{Duration, Instant};
 
 use crate::cli::Args;
-use crate::core::file_io::save_keypair_to_files;
-use crate::core::keypair::KeyPair;
-use crate::core::pattern::Pattern;
+use crate::core::backend::KeyBackend;
+use crate::core::file_io::KeyWriter;
+use crate::core::install::{SSH_CONFIG, add_host_block, expand_home, install_key_pair};
+use crate::core::pattern::{Folding, Pattern, estimate_time};
+use crate::core::score::format_score;
+use crate::worker::LivePatterns;
 use crate::worker::message::WorkerMessage;
 
+use super::leaderboard::Leaderboard;
+use super::limits::{SearchLimits, StopReason};
 use super::ntfy::notify;
 
 const RATE_WINDOW: Duration = Duration::from_secs(1);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Above this many patterns the progress display shows one combined line
+/// and the summary only lists the patterns that were found.
+const MAX_LISTED_PATTERNS: usize = 10;
+/// Characters of the best key shown in the progress display.
+const SHOWN_TAIL_LEN: usize = 16;
 
-struct ManagerState {
+#[derive(Default)]
+struct PatternHits {
+    found: usize,
+    filenames: Vec<String>,
+    /// Total attempts at the moment the pattern reached its quota and was
+    /// dropped from the search.
+    completed_at_attempts: Option<u64>,
+}
+
+/// Probability that a key matches a pattern, computed once as it can take a
+/// walk over a whole dictionary.
+struct PatternStats {
+    probability: Option<f64>,
+    is_exact: bool,
+}
+
+struct ManagerState<B: KeyBackend> {
     total_attempts: u64,
-    pattern_key_pairs: HashMap<Pattern, Vec<KeyPair>>,
+    pattern_hits: HashMap<Pattern, PatternHits>,
+    pattern_stats: HashMap<Pattern, PatternStats>,
+    /// Probability that a key matches none of the patterns still searched
+    /// for, updated when one of them is dropped.
+    miss_probability: f64,
+    n_live_patterns: usize,
     progress_bar: ProgressBar,
     attempt_history: VecDeque<(Instant, u64)>,
     attempt_history_total: u64,
+    leaderboard: Option<Leaderboard>,
+    writer: KeyWriter<B>,
 }
 
-impl ManagerState {
-    fn new() -> Self {
+impl<B: KeyBackend> ManagerState<B> {
+    fn new(patterns: &[Pattern], leaderboard: Option<Leaderboard>, writer: KeyWriter<B>) -> Self {
         let progress_bar = ProgressBar::new_spinner();
         progress_bar.set_style(
             ProgressStyle::default_spinner()
@@ -32,13 +69,30 @@ impl ManagerState {
         );
         progress_bar.enable_steady_tick(Duration::from_millis(100));
 
-        Self {
+        let layout = writer.backend().layout();
+        let mut state = Self {
             total_attempts: 0,
-            pattern_key_pairs: HashMap::new(),
+            pattern_hits: HashMap::new(),
+            pattern_stats: patterns
+                .iter()
+                .map(|pattern| {
+                    let stats = PatternStats {
+                        probability: pattern.probability(layout),
+                        is_exact: pattern.is_probability_exact(layout),
+                    };
+                    (pattern.clone(), stats)
+                })
+                .collect(),
+            miss_probability: 1.0,
+            n_live_patterns: 0,
             progress_bar,
             attempt_history: VecDeque::new(),
             attempt_history_total: 0,
-        }
+            leaderboard,
+            writer,
+        };
+        state.update_miss_probability(patterns);
+        state
     }
 
     fn update_attempts(&mut self, attempts: u64, timestamp: Instant) {
@@ -48,49 +102,233 @@ impl ManagerState {
         self.prune_attempt_history(timestamp);
     }
 
-    fn add_key_pair(&mut self, pattern: Pattern, key_pair: KeyPair) {
-        self.pattern_key_pairs
-            .entry(pattern)
-            .or_default()
-            .push(key_pair);
+    fn add_hit(&mut self, pattern: Pattern) {
+        self.pattern_hits.entry(pattern).or_default().found += 1;
+    }
+
+    fn add_saved_file(&mut self, pattern: &Pattern, filename: String) {
+        if let Some(hits) = self.pattern_hits.get_mut(pattern) {
+            hits.filenames.push(filename);
+        }
+    }
+
+    fn mark_completed(&mut self, pattern: &Pattern, patterns: &[Pattern]) {
+        let total_attempts = self.total_attempts;
+        if let Some(hits) = self.pattern_hits.get_mut(pattern) {
+            hits.completed_at_attempts = Some(total_attempts);
+        }
+        self.update_miss_probability(patterns);
+    }
+
+    fn is_completed(&self, pattern: &Pattern) -> bool {
+        self.pattern_hits
+            .get(pattern)
+            .is_some_and(|hits| hits.completed_at_attempts.is_some())
+    }
+
+    fn update_miss_probability(&mut self, patterns: &[Pattern]) {
+        let live: Vec<&Pattern> = patterns
+            .iter()
+            .filter(|pattern| !self.is_completed(pattern))
+            .collect();
+        self.n_live_patterns = live.len();
+        self.miss_probability = live
+            .into_iter()
+            .filter_map(|pattern| self.stats(pattern).probability)
+            .map(|prob| 1.0 - prob)
+            .product();
+    }
+
+    fn stats(&self, pattern: &Pattern) -> &PatternStats {
+        &self.pattern_stats[pattern]
     }
 
     fn get_pattern_hits(&self, pattern: &Pattern) -> usize {
-        self.pattern_key_pairs
+        self.pattern_hits.get(pattern).map_or(0, |hits| hits.found)
+    }
+
+    fn total_hits(&self) -> usize {
+        self.pattern_hits.values().map(|hits| hits.found).sum()
+    }
+
+    /// Attempts that counted towards `pattern`, i.e. excluding those made
+    /// after it had already reached its quota.
+    fn pattern_attempts(&self, pattern: &Pattern) -> u64 {
+        self.pattern_hits
             .get(pattern)
-            .map_or(0, |keys| keys.len())
+            .and_then(|hits| hits.completed_at_attempts)
+            .unwrap_or(self.total_attempts)
     }
 }
-pub fn run_manager(rx: Receiver<WorkerMessage>, start: Instant, patterns: &[Pattern], args: &Args) {
-    let mut state = ManagerState::new();
 
-    loop {
-        if let Ok(msg) = rx.recv() {
-            let now = Instant::now();
-            state.update_attempts(msg.attempts, now);
-            state
-                .progress_bar
-                .set_message(update_progress_message(&state, patterns, start));
-
-            if let Some(search_hit) = msg.search_hit {
-                if let Err(e) =
-                    handle_search_hit(&mut state, search_hit.pattern, search_hit.key_pair, args)
-                {
-                    state
-                        .progress_bar
-                        .println(format!("Error handling search hit: {}", e));
-                }
+#[allow(clippy::too_many_arguments)]
+pub fn run_manager<B: KeyBackend>(
+    rx: Receiver<WorkerMessage<B::KeyPair>>,
+    start: Instant,
+    patterns: &[Pattern],
+    live_patterns: &LivePatterns,
+    leaderboard: Option<Leaderboard>,
+    writer: KeyWriter<B>,
+    limits: &SearchLimits,
+    stop_flag: &AtomicBool,
+    args: &Args,
+) {
+    let mut state = ManagerState::new(patterns, leaderboard, writer);
 
-                if args.stop_after_match {
-                    state.progress_bar.finish_and_clear();
-                    break;
+    let reason = loop {
+        if stop_flag.load(Ordering::Relaxed) {
+            break StopReason::Interrupted;
+        }
+
+        let msg = match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(msg) => msg,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(reason) = check_limits(&state, limits, start) {
+                    break reason;
                 }
+                continue;
             }
+            // Every worker has dropped its sender.
+            Err(RecvTimeoutError::Disconnected) => break StopReason::WorkersStopped,
+        };
+
+        if let Some(reason) = handle_message(
+            &mut state,
+            msg,
+            patterns,
+            live_patterns,
+            limits,
+            start,
+            args,
+        ) {
+            break reason;
         }
+        if let Some(reason) = check_limits(&state, limits, start) {
+            break reason;
+        }
+    };
+
+    // Let the workers finish their current batch and collect what they report
+    // on the way out, so the summary accounts for every attempt.
+    stop_flag.store(true, Ordering::Relaxed);
+    while let Ok(msg) = rx.recv() {
+        handle_message(
+            &mut state,
+            msg,
+            patterns,
+            live_patterns,
+            limits,
+            start,
+            args,
+        );
     }
+
+    state.progress_bar.finish_and_clear();
+    print_summary(&state, patterns, start, reason);
 }
 
-fn update_progress_message(state: &ManagerState, patterns: &[Pattern], start: Instant) -> String {
+fn handle_message<B: KeyBackend>(
+    state: &mut ManagerState<B>,
+    msg: WorkerMessage<B::KeyPair>,
+    patterns: &[Pattern],
+    live_patterns: &LivePatterns,
+    limits: &SearchLimits,
+    start: Instant,
+    args: &Args,
+) -> Option<StopReason> {
+    let now = Instant::now();
+    state.update_attempts(msg.attempts, now);
+    state
+        .progress_bar
+        .set_message(update_progress_message(state, patterns, limits, start));
+
+    if let (Some(scored_key), Some(leaderboard)) = (msg.scored_key, &mut state.leaderboard) {
+        match leaderboard.offer(scored_key, &mut state.writer) {
+            Ok(Some(entry)) => state.progress_bar.println(format!(
+                "🏆 New top key with score {}: saved to '{}'",
+                format_score(entry.score),
+                entry.path.display()
+            )),
+            Ok(None) => {}
+            Err(e) => state
+                .progress_bar
+                .println(format!("Error saving scored key: {}", e)),
+        }
+    }
+
+    let search_hit = msg.search_hit?;
+    let pattern = search_hit.pattern;
+    // Workers may still report a pattern for a short while after it was
+    // dropped from the live list or after the search has been stopped.
+    if limits.is_quota_reached(&pattern, state.get_pattern_hits(&pattern))
+        || limits
+            .max_keys
+            .is_some_and(|max_keys| state.total_hits() >= max_keys)
+    {
+        return None;
+    }
+
+    if let Err(e) = handle_search_hit(
+        state,
+        pattern.clone(),
+        &search_hit.key_pair,
+        search_hit.word,
+        args,
+    ) {
+        state
+            .progress_bar
+            .println(format!("Error handling search hit: {}", e));
+    }
+
+    if limits.is_quota_reached(&pattern, state.get_pattern_hits(&pattern)) {
+        state.mark_completed(&pattern, patterns);
+        drop_pattern(live_patterns, &pattern);
+        if live_patterns.read().unwrap().is_empty() {
+            return Some(StopReason::QuotasReached);
+        }
+    }
+
+    None
+}
+
+fn check_limits<B: KeyBackend>(
+    state: &ManagerState<B>,
+    limits: &SearchLimits,
+    start: Instant,
+) -> Option<StopReason> {
+    if limits
+        .max_keys
+        .is_some_and(|max_keys| state.total_hits() >= max_keys)
+    {
+        return Some(StopReason::MaxKeys);
+    }
+    if limits
+        .max_attempts
+        .is_some_and(|max_attempts| state.total_attempts >= max_attempts)
+    {
+        return Some(StopReason::MaxAttempts);
+    }
+    if limits
+        .timeout
+        .is_some_and(|timeout| start.elapsed() >= timeout)
+    {
+        return Some(StopReason::Timeout);
+    }
+    None
+}
+
+fn drop_pattern(live_patterns: &LivePatterns, pattern: &Pattern) {
+    let mut live = live_patterns.write().unwrap();
+    let remaining = live.without(pattern);
+    *live = Arc::new(remaining);
+}
+
+fn update_progress_message<B: KeyBackend>(
+    state: &ManagerState<B>,
+    patterns: &[Pattern],
+    limits: &SearchLimits,
+    start: Instant,
+) -> String {
     let duration = start.elapsed();
     let elapsed_secs = duration.as_secs_f64();
     let avg_rate = if elapsed_secs > 0.0 {
@@ -107,23 +345,47 @@ fn update_progress_message(state: &ManagerState, patterns: &[Pattern], start: In
         avg_rate.to_formatted_string(&Locale::en)
     );
 
+    if let Some(best) = state
+        .leaderboard
+        .as_ref()
+        .and_then(|leaderboard| leaderboard.best())
+    {
+        let tail_start = best.text.len().saturating_sub(SHOWN_TAIL_LEN);
+        progress_msg = format!(
+            "{}\nBest score: {} (…{})",
+            progress_msg,
+            format_score(best.score),
+            &best.text[tail_start..]
+        );
+    }
+
+    if patterns.len() > MAX_LISTED_PATTERNS {
+        return format!(
+            "{}\n{} | {}",
+            progress_msg,
+            format_combined_stats(state, avg_rate as f64),
+            format_hits_message(state.total_hits(), limits.max_keys)
+        );
+    }
+
     for pattern in patterns {
         progress_msg = format!(
             "{}\n{}",
             progress_msg,
-            format_pattern_stats(pattern, avg_rate as f64)
+            format_pattern_stats(pattern, state.stats(pattern), avg_rate as f64)
         );
 
         let n_hits = state.get_pattern_hits(pattern);
-        if n_hits > 0 {
-            progress_msg = format!("{} | {}", progress_msg, format_hits_message(n_hits));
+        let quota = limits.quota(pattern);
+        if n_hits > 0 || quota.is_some() {
+            progress_msg = format!("{} | {}", progress_msg, format_hits_message(n_hits, quota));
         }
     }
 
     progress_msg
 }
 
-impl ManagerState {
+impl<B: KeyBackend> ManagerState<B> {
     fn prune_attempt_history(&mut self, timestamp: Instant) {
         while let Some(&(time, attempts)) = self.attempt_history.front() {
             if timestamp.duration_since(time) > RATE_WINDOW {
@@ -144,60 +406,139 @@ impl ManagerState {
     }
 }
 
-fn format_pattern_stats(pattern: &Pattern, rate: f64) -> String {
-    let pattern_str = match pattern {
-        Pattern::Suffix(s) => s.as_str(),
-        Pattern::Regex(r) => r.as_str(),
-    };
+fn pattern_text(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Suffix(s, _) if s.starts_with('/') && s.ends_with('/') => format!("suffix:{}", s),
+        Pattern::Suffix(s, _) => s.clone(),
+        Pattern::FoldedSuffix(s, Folding::CaseInsensitive, _) => format!("ci:{}", s),
+        Pattern::FoldedSuffix(s, Folding::LookAlike, _) => format!("lookalike:{}", s),
+        Pattern::Prefix(s, _) => format!("prefix:{}", s),
+        Pattern::At(offset, s, _) => format!("at:{}:{}", offset, s),
+        Pattern::Contains(s) => format!("contains:{}", s),
+        Pattern::Hex(s, _) => format!("hex:{}", s),
+        Pattern::Dictionary(d) => format!("dict:{}", d.name),
+        Pattern::Regex(r) => r.as_str().to_string(),
+        Pattern::Fingerprint(inner) => format!("fp:{}", pattern_text(inner)),
+        Pattern::And(children) => join_operands(children, " & "),
+        Pattern::Or(children) => join_operands(children, " | "),
+        Pattern::Not(inner) => format!("!{}", operand_text(inner)),
+    }
+}
 
-    match pattern.probability() {
+fn join_operands(children: &[Pattern], separator: &str) -> String {
+    children
+        .iter()
+        .map(operand_text)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn operand_text(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::And(_) | Pattern::Or(_) => format!("({})", pattern_text(pattern)),
+        _ => pattern_text(pattern),
+    }
+}
+
+fn format_pattern_stats(pattern: &Pattern, stats: &PatternStats, rate: f64) -> String {
+    let pattern_str = pattern_text(pattern);
+
+    match stats.probability {
         Some(prob) => {
             let expected_attempts = (1.0 / prob) as u64;
-            let est_time = pattern
-                .estimate_time(rate)
-                .unwrap_or_default()
-                .split_whitespace()
-                .take(2)
-                .collect::<Vec<_>>()
-                .join(" ");
+            let est_time = format_estimate(prob, rate);
 
-            format!(
-                "Pattern '{}': 1 in {} (est. {})",
-                pattern_str,
-                expected_attempts.to_formatted_string(&Locale::en),
-                est_time
-            )
+            if stats.is_exact {
+                format!(
+                    "Pattern '{}': 1 in {} (est. {})",
+                    pattern_str,
+                    expected_attempts.to_formatted_string(&Locale::en),
+                    est_time
+                )
+            } else {
+                format!(
+                    "Pattern '{}': ~1 in {} (approx., est. {})",
+                    pattern_str,
+                    expected_attempts.to_formatted_string(&Locale::en),
+                    est_time
+                )
+            }
         }
         None => format!("Pattern '{}': regex pattern (no estimate)", pattern_str),
     }
 }
 
-fn format_hits_message(n_hits: usize) -> String {
-    let is_plural = if n_hits == 1 { "" } else { "s" };
+/// Odds that a key matches any of the patterns still searched for, treating
+/// them as independent.
+fn format_combined_stats<B: KeyBackend>(state: &ManagerState<B>, rate: f64) -> String {
+    let probability = 1.0 - state.miss_probability;
     format!(
-        "{} key{} found",
-        n_hits.to_formatted_string(&Locale::en),
-        is_plural
+        "{} patterns: ~1 in {} for any (est. {})",
+        state.n_live_patterns.to_formatted_string(&Locale::en),
+        ((1.0 / probability) as u64).to_formatted_string(&Locale::en),
+        format_estimate(probability, rate)
     )
 }
 
-fn handle_search_hit(
-    state: &mut ManagerState,
+/// The two largest units of the expected time, e.g. `3h 12m`.
+fn format_estimate(probability: f64, rate: f64) -> String {
+    estimate_time(probability, rate)
+        .split_whitespace()
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn format_hits_message(n_hits: usize, quota: Option<usize>) -> String {
+    match quota {
+        Some(quota) => format!(
+            "{}/{} keys found",
+            n_hits.to_formatted_string(&Locale::en),
+            quota.to_formatted_string(&Locale::en)
+        ),
+        None => {
+            let is_plural = if n_hits == 1 { "" } else { "s" };
+            format!(
+                "{} key{} found",
+                n_hits.to_formatted_string(&Locale::en),
+                is_plural
+            )
+        }
+    }
+}
+
+fn handle_search_hit<B: KeyBackend>(
+    state: &mut ManagerState<B>,
     pattern: Pattern,
-    key_pair: KeyPair,
+    key_pair: &B::KeyPair,
+    word: Option<String>,
     args: &Args,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    state.add_key_pair(pattern.clone(), key_pair.clone());
+    state.add_hit(pattern.clone());
 
-    let filename = pattern.to_filename();
-    state
-        .progress_bar
-        .println(format!("✨ Found matching key for pattern '{}'", pattern));
+    let pattern_name = match &word {
+        Some(word) => word.clone(),
+        None => pattern.filename_stem(),
+    };
+    match &word {
+        Some(word) => state.progress_bar.println(format!(
+            "✨ Found matching key for pattern '{}': '{}'",
+            pattern, word
+        )),
+        None => state
+            .progress_bar
+            .println(format!("✨ Found matching key for pattern '{}'", pattern)),
+    }
 
-    save_keypair_to_files(&key_pair, &filename)?;
+    let path = state.writer.save(key_pair, &pattern_name)?;
     state
         .progress_bar
-        .println(format!("Key saved to 'out/{}'", filename));
+        .println(state.writer.backend().saved_message(key_pair, &path));
+    state.add_saved_file(&pattern, path.display().to_string());
+
+    if let Some(install_path) = &args.install {
+        install(state, &path, install_path, args.ssh_host.as_deref())?;
+    }
 
     if let Some(topic) = &args.ntfy {
         notify(topic, &format!("Found key matching pattern '{}'", pattern))?;
@@ -205,3 +546,303 @@ fn handle_search_hit(
 
     Ok(())
 }
+
+/// Installs the saved key pair for `--install` and `--ssh-host`.
+fn install<B: KeyBackend>(
+    state: &ManagerState<B>,
+    saved: &Path,
+    install_path: &Path,
+    ssh_host: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let installed = install_key_pair(saved, install_path)?;
+    if let Some(backup) = &installed.backup {
+        state.progress_bar.println(format!(
+            "Backed up the previous key to '{}'",
+            backup.display()
+        ));
+    }
+    state
+        .progress_bar
+        .println(format!("Key installed as '{}'", installed.path.display()));
+
+    if let Some(host) = ssh_host {
+        let config = expand_home(Path::new(SSH_CONFIG))?;
+        if add_host_block(&config, host, &installed.path)? {
+            state
+                .progress_bar
+                .println(format!("Added 'Host {}' to '{}'", host, config.display()));
+        } else {
+            state.progress_bar.println(format!(
+                "'{}' already has a 'Host {}' block, left it unchanged",
+                config.display(),
+                host
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn print_summary<B: KeyBackend>(
+    state: &ManagerState<B>,
+    patterns: &[Pattern],
+    start: Instant,
+    reason: StopReason,
+) {
+    let elapsed = start.elapsed();
+    let elapsed_secs = elapsed.as_secs_f64();
+    let avg_rate = if elapsed_secs > 0.0 {
+        (state.total_attempts as f64 / elapsed_secs).round() as u64
+    } else {
+        0
+    };
+
+    println!("Search stopped: {}.", reason);
+    println!(
+        "Attempts: {} in {} ({} keys/sec avg)",
+        state.total_attempts.to_formatted_string(&Locale::en),
+        humantime::format_duration(Duration::from_secs(elapsed.as_secs())),
+        avg_rate.to_formatted_string(&Locale::en)
+    );
+
+    let list_all = patterns.len() <= MAX_LISTED_PATTERNS;
+    let mut n_unlisted = 0;
+    for pattern in patterns {
+        let n_hits = state.get_pattern_hits(pattern);
+        if !list_all && n_hits == 0 {
+            n_unlisted += 1;
+            continue;
+        }
+        let stats = state.stats(pattern);
+        let luck = stats
+            .probability
+            .map(|prob| {
+                let luck = format_luck(n_hits, state.pattern_attempts(pattern) as f64 * prob);
+                if stats.is_exact {
+                    luck
+                } else {
+                    format!("{}, approx.", luck)
+                }
+            })
+            .unwrap_or_else(|| "no estimate".to_string());
+        println!(
+            "Pattern '{}': {} ({})",
+            pattern,
+            format_hits_message(n_hits, None),
+            luck
+        );
+
+        if let Some(hits) = state.pattern_hits.get(pattern) {
+            for filename in &hits.filenames {
+                println!("  {}", filename);
+            }
+        }
+    }
+
+    if n_unlisted > 0 {
+        println!(
+            "{} other patterns: no keys found",
+            n_unlisted.to_formatted_string(&Locale::en)
+        );
+    }
+
+    if let Some(leaderboard) = &state.leaderboard {
+        println!("Best keys:");
+        for entry in leaderboard.entries() {
+            println!(
+                "  {:>6}  {}  {}",
+                format_score(entry.score),
+                entry.text,
+                entry.path.display()
+            );
+        }
+    }
+}
+
+/// Compares the number of hits with the number expected from the pattern's
+/// probability over the attempts made.
+fn format_luck(n_hits: usize, expected_hits: f64) -> String {
+    if n_hits == 0 {
+        // Hits are Poisson distributed, so this is the chance that a run of
+        // the same length would have found at least one key.
+        let chance = 1.0 - (-expected_hits).exp();
+        format!(
+            "expected {}, {}% chance of at least one",
+            format_small(expected_hits),
+            format_small(chance * 100.0)
+        )
+    } else {
+        format!(
+            "expected {}, {:.2}x as lucky as average",
+            format_small(expected_hits),
+            n_hits as f64 / expected_hits
+        )
+    }
+}
+
+fn format_small(value: f64) -> String {
+    if value == 0.0 || value >= 0.01 {
+        format!("{:.2}", value)
+    } else {
+        format!("{:.1e}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::RwLock;
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::core::keypair::{Ed25519Backend, Strategy};
+    use crate::core::pattern_set::PatternSet;
+    use crate::worker::message::SearchHit;
+
+    const BACKEND: Ed25519Backend = Ed25519Backend {
+        strategy: Strategy::Seed,
+    };
+
+    /// A run for `options` with everything the manager needs, saving keys
+    /// into a fresh directory.
+    struct Run {
+        args: Args,
+        patterns: Vec<Pattern>,
+        live_patterns: LivePatterns,
+        limits: SearchLimits,
+        state: ManagerState<Ed25519Backend>,
+        dir: PathBuf,
+    }
+
+    impl Run {
+        fn new(test: &str, options: &[&str]) -> Self {
+            let args =
+                Args::parse_from(std::iter::once("vanity-ssh-rs").chain(options.iter().copied()));
+            let patterns: Vec<Pattern> = args
+                .patterns
+                .iter()
+                .map(|text| Pattern::new(text.clone()).unwrap())
+                .collect();
+            let live_patterns = Arc::new(RwLock::new(Arc::new(PatternSet::new(patterns.clone()))));
+            let limits = SearchLimits::from_args(&args, &patterns).unwrap();
+            let dir = std::env::temp_dir().join(format!(
+                "vanity-ssh-rs-manager-{}-{}",
+                test,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            let writer = KeyWriter::new(
+                BACKEND,
+                dir.clone(),
+                "{pattern}".to_string(),
+                "test@vanity".to_string(),
+                None,
+            )
+            .unwrap();
+            let state = ManagerState::new(&patterns, None, writer);
+            Self {
+                args,
+                patterns,
+                live_patterns,
+                limits,
+                state,
+                dir,
+            }
+        }
+
+        /// Handles a message of 10 attempts with a hit for the `i`-th pattern.
+        fn hit(&mut self, i: usize) -> Option<StopReason> {
+            let msg = WorkerMessage {
+                attempts: 10,
+                search_hit: Some(SearchHit {
+                    key_pair: BACKEND.generate_batch(1).remove(0),
+                    pattern: self.patterns[i].clone(),
+                    word: None,
+                }),
+                scored_key: None,
+            };
+            handle_message(
+                &mut self.state,
+                msg,
+                &self.patterns,
+                &self.live_patterns,
+                &self.limits,
+                Instant::now(),
+                &self.args,
+            )
+        }
+
+        fn is_live(&self, i: usize) -> bool {
+            self.live_patterns
+                .read()
+                .unwrap()
+                .find_match(|_| None, |p| p == &self.patterns[i])
+                .is_some()
+        }
+
+        fn check_limits(&self) -> Option<StopReason> {
+            check_limits(&self.state, &self.limits, Instant::now())
+        }
+
+        fn n_saved(&self) -> usize {
+            fs::read_dir(&self.dir).map_or(0, |dir| dir.count())
+        }
+    }
+
+    impl Drop for Run {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn patterns_are_dropped_once_their_quota_is_met() {
+        let mut run = Run::new("quota", &["contains:abc", "contains:xyz", "-c", "1,2"]);
+        assert_eq!(run.state.n_live_patterns, 2);
+
+        assert_eq!(run.hit(0), None);
+        assert!(!run.is_live(0));
+        assert!(run.is_live(1));
+        assert_eq!(run.state.n_live_patterns, 1);
+        assert_eq!(run.state.pattern_attempts(&run.patterns[0]), 10);
+
+        // A worker may still report a pattern that was just dropped.
+        assert_eq!(run.hit(0), None);
+        assert_eq!(run.state.get_pattern_hits(&run.patterns[0]), 1);
+        assert_eq!(run.state.pattern_attempts(&run.patterns[0]), 10);
+
+        assert_eq!(run.hit(1), None);
+        assert!(run.is_live(1));
+        assert_eq!(run.hit(1), Some(StopReason::QuotasReached));
+        assert!(run.live_patterns.read().unwrap().is_empty());
+        assert_eq!(run.state.total_hits(), 3);
+        // Each OpenSSH key is a private and a public key file.
+        assert_eq!(run.n_saved(), 2 * 3);
+    }
+
+    #[test]
+    fn max_keys_stops_the_run() {
+        let mut run = Run::new("max-keys", &["contains:abc", "--max-keys", "2"]);
+        assert_eq!(run.hit(0), None);
+        assert_eq!(run.check_limits(), None);
+        assert_eq!(run.hit(0), None);
+        assert_eq!(run.check_limits(), Some(StopReason::MaxKeys));
+
+        // Hits reported while the workers wind down are not saved.
+        assert_eq!(run.hit(0), None);
+        assert_eq!(run.state.total_hits(), 2);
+        assert_eq!(run.n_saved(), 2 * 2);
+        assert!(run.is_live(0));
+    }
+
+    #[test]
+    fn max_attempts_stops_the_run() {
+        let mut run = Run::new("max-attempts", &["contains:abc", "--max-attempts", "25"]);
+        run.hit(0);
+        run.hit(0);
+        assert_eq!(run.check_limits(), None);
+        run.hit(0);
+        assert_eq!(run.check_limits(), Some(StopReason::MaxAttempts));
+    }
+}
@@ -1,2 +1,5 @@
+pub mod leaderboard;
+pub mod limits;
+#[allow(clippy::module_inception)]
 pub mod manager;
 pub mod ntfy;
@@ -1,17 +1,88 @@
-use crate::core::keypair::{BATCH_SIZE, KeyPair, generate_keypair_batch};
-use crate::core::pattern::{Pattern, public_key_matches_pattern};
+use crate::core::backend::{BatchKeyPair, KeyBackend};
+use crate::core::keypair::BATCH_SIZE;
+use crate::core::pattern_set::PatternSet;
+use crate::core::score::Scorer;
+use crate::worker::message::{ScoredKey, SearchHit};
 
-pub fn generate_and_check_batch(patterns: &[Pattern]) -> Option<(KeyPair, Pattern)> {
-    let keypairs = generate_keypair_batch(BATCH_SIZE);
+pub struct BatchResult<K> {
+    pub search_hit: Option<SearchHit<K>>,
+    /// The best key of the batch that scored above the threshold.
+    pub scored_key: Option<ScoredKey<K>>,
+}
+
+/// Generates a batch of keys and returns the first one matching a pattern.
+/// With `scoring`, every key is also scored and the best one beating the
+/// given threshold is returned too. With `lock_memory` the secrets of the
+/// returned keys are moved into locked memory.
+pub fn generate_and_check_batch<B: KeyBackend>(
+    backend: &B,
+    patterns: &PatternSet,
+    scoring: Option<(&Scorer, f64)>,
+    lock_memory: bool,
+) -> BatchResult<B::KeyPair> {
+    let mut keypairs = backend.generate_batch(BATCH_SIZE);
+    let mut best: Option<(usize, f64)> = None;
+    let mut hit = None;
+    let mut text = Vec::new();
+
+    for (i, keypair) in keypairs.iter().enumerate() {
+        if let Some((scorer, threshold)) = scoring {
+            backend.variable_text(keypair, &mut text);
+            let score = scorer.score(&text);
+            let best_so_far = best.map_or(threshold, |(_, best)| best);
+            if score > best_so_far {
+                best = Some((i, score));
+            }
+        }
 
-    for keypair in keypairs {
-        if let Some(pattern) = patterns
-            .iter()
-            .find(|p| public_key_matches_pattern(&keypair, p))
+        if hit.is_none()
+            && let Some(pattern) = backend.find_match(keypair, patterns)
         {
-            return Some((keypair, pattern.clone()));
+            hit = Some((i, pattern, backend.matched_word(keypair, pattern)));
+            if scoring.is_none() {
+                break;
+            }
         }
     }
 
-    None
+    // Keys leave the batch through `take`, so the secrets of the batch are
+    // all wiped when it is dropped. Only a key that is both the best scored
+    // one and a hit is sent twice.
+    let hit_index = hit.as_ref().map(|(i, _, _)| *i);
+    let scored_key = best.map(|(i, score)| {
+        let text = backend.text(&keypairs[i]);
+        let mut key_pair = if hit_index == Some(i) {
+            keypairs[i].duplicate()
+        } else {
+            keypairs[i].take()
+        };
+        lock_secret(&mut key_pair, lock_memory);
+        ScoredKey {
+            key_pair,
+            score,
+            text,
+        }
+    });
+    let search_hit = hit.map(|(i, pattern, word)| {
+        let mut key_pair = keypairs[i].take();
+        lock_secret(&mut key_pair, lock_memory);
+        SearchHit {
+            key_pair,
+            pattern: pattern.clone(),
+            word,
+        }
+    });
+
+    BatchResult {
+        search_hit,
+        scored_key,
+    }
+}
+
+fn lock_secret(key_pair: &mut impl BatchKeyPair, lock_memory: bool) {
+    // Locking was checked to work at startup. Should a hit still not fit
+    // under the limit, saving it unlocked beats losing it.
+    if lock_memory {
+        let _ = key_pair.lock();
+    }
 }
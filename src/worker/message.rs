@@ -1,11 +1,23 @@
-use crate::core::{keypair::KeyPair, pattern::Pattern};
+use crate::core::pattern::Pattern;
 
-pub struct SearchHit {
-    pub key_pair: KeyPair,
+pub struct SearchHit<K> {
+    pub key_pair: K,
     pub pattern: Pattern,
+    /// The word that matched, for dictionary patterns.
+    pub word: Option<String>,
 }
 
-pub struct WorkerMessage {
+/// The best-scoring key of a batch, sent in scoring mode.
+pub struct ScoredKey<K> {
+    pub key_pair: K,
+    pub score: f64,
+    /// The key's text as patterns see it.
+    pub text: String,
+}
+
+/// `K` is the [`crate::core::backend::KeyBackend::KeyPair`] of the search.
+pub struct WorkerMessage<K> {
     pub attempts: u64,
-    pub search_hit: Option<SearchHit>,
+    pub search_hit: Option<SearchHit<K>>,
+    pub scored_key: Option<ScoredKey<K>>,
 }
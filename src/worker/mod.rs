@@ -2,4 +2,4 @@ pub mod generator;
 pub mod message;
 pub mod thread;
 
-pub use thread::spawn_worker_threads;
+pub use thread::{LivePatterns, ScoreThreshold, new_score_threshold, spawn_worker_threads};
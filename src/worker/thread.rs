@@ -1,78 +1,117 @@
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, RwLock};
 use std::thread;
 
+use crate::core::backend::KeyBackend;
 use crate::core::keypair::BATCH_SIZE;
-use crate::core::pattern::Pattern;
+use crate::core::pattern_set::PatternSet;
+use crate::core::score::Scorer;
 use crate::worker::generator::generate_and_check_batch;
 use crate::worker::message::WorkerMessage;
 
-use super::message::SearchHit;
+use super::message::{ScoredKey, SearchHit};
 
-pub fn spawn_worker_threads(
+/// Patterns the workers are still searching for. The manager swaps in a new
+/// list when a pattern has reached its quota.
+pub type LivePatterns = Arc<RwLock<Arc<PatternSet>>>;
+
+/// Score a key must beat to be sent to the manager in scoring mode, stored
+/// as the bits of an `f64`. The manager raises it as its list of best keys
+/// fills up.
+pub type ScoreThreshold = Arc<AtomicU64>;
+
+pub fn new_score_threshold() -> ScoreThreshold {
+    Arc::new(AtomicU64::new(f64::NEG_INFINITY.to_bits()))
+}
+
+pub fn spawn_worker_threads<B: KeyBackend>(
     n_threads: usize,
-    patterns: Arc<Vec<Pattern>>,
-    tx: Sender<WorkerMessage>,
+    patterns: LivePatterns,
+    backend: B,
+    scoring: Option<(Arc<Scorer>, ScoreThreshold)>,
+    lock_memory: bool,
+    tx: Sender<WorkerMessage<B::KeyPair>>,
     stop_flag: Arc<AtomicBool>,
 ) -> Vec<thread::JoinHandle<()>> {
     (0..n_threads)
         .map(|_| {
             let tx = tx.clone();
             let patterns = Arc::clone(&patterns);
+            let scoring = scoring.clone();
             let stop_flag = Arc::clone(&stop_flag);
-            thread::spawn(move || run_worker_loop(patterns, tx, stop_flag))
+            let backend = backend.clone();
+            thread::spawn(move || {
+                run_worker_loop(patterns, backend, scoring, lock_memory, tx, stop_flag)
+            })
         })
         .collect()
 }
 
-pub fn run_worker_loop(
-    patterns: Arc<Vec<Pattern>>,
-    tx: Sender<WorkerMessage>,
+pub fn run_worker_loop<B: KeyBackend>(
+    patterns: LivePatterns,
+    backend: B,
+    scoring: Option<(Arc<Scorer>, ScoreThreshold)>,
+    lock_memory: bool,
+    tx: Sender<WorkerMessage<B::KeyPair>>,
     stop_flag: Arc<AtomicBool>,
 ) {
     let mut local_attempts = 0;
 
     loop {
         if stop_flag.load(Ordering::Relaxed) {
+            send_progress_update(&tx, local_attempts);
             break;
         }
 
-        let result = generate_and_check_batch(&patterns);
+        let current_patterns = Arc::clone(&patterns.read().unwrap());
+        let current_scoring = scoring.as_ref().map(|(scorer, threshold)| {
+            (
+                scorer.as_ref(),
+                f64::from_bits(threshold.load(Ordering::Relaxed)),
+            )
+        });
+        let result =
+            generate_and_check_batch(&backend, &current_patterns, current_scoring, lock_memory);
         local_attempts += BATCH_SIZE as u64;
 
-        if let Some((key_pair, pattern)) = result {
-            send_success(&tx, key_pair, local_attempts, pattern);
-            break;
+        if result.search_hit.is_some() || result.scored_key.is_some() {
+            if !send_success(&tx, result.search_hit, result.scored_key, local_attempts) {
+                break;
+            }
+            local_attempts = 0;
+            continue;
         }
 
         if local_attempts >= 1000 {
-            send_progress_update(&tx, local_attempts);
+            if !send_progress_update(&tx, local_attempts) {
+                break;
+            }
             local_attempts = 0;
         }
     }
 }
 
-pub fn send_success(
-    tx: &Sender<WorkerMessage>,
-    key_pair: crate::core::keypair::KeyPair,
+/// Returns `false` once the manager has hung up and the worker should exit.
+pub fn send_success<K>(
+    tx: &Sender<WorkerMessage<K>>,
+    search_hit: Option<SearchHit<K>>,
+    scored_key: Option<ScoredKey<K>>,
     attempts: u64,
-    pattern: Pattern,
-) {
+) -> bool {
     tx.send(WorkerMessage {
         attempts,
-        search_hit: Some(SearchHit {
-            key_pair,
-            pattern: pattern.clone(),
-        }),
+        search_hit,
+        scored_key,
     })
-    .unwrap();
+    .is_ok()
 }
 
-pub fn send_progress_update(tx: &Sender<WorkerMessage>, attempts: u64) {
+pub fn send_progress_update<K>(tx: &Sender<WorkerMessage<K>>, attempts: u64) -> bool {
     tx.send(WorkerMessage {
         attempts,
         search_hit: None,
+        scored_key: None,
     })
-    .unwrap();
+    .is_ok()
 }
@@ -0,0 +1,116 @@
+//! The contract every key backend has to keep with the workers, the manager
+//! and the pattern parser, checked once for each backend.
+
+use std::fs;
+
+use vanity_ssh_rs::core::backend::KeyBackend;
+use vanity_ssh_rs::core::ecdsa::{Curve, EcdsaBackend};
+use vanity_ssh_rs::core::keypair::{Ed25519Backend, Strategy};
+use vanity_ssh_rs::core::onion::OnionBackend;
+use vanity_ssh_rs::core::pattern::Pattern;
+use vanity_ssh_rs::core::pattern_set::PatternSet;
+use vanity_ssh_rs::core::rsa::RsaBackend;
+use vanity_ssh_rs::core::wireguard::WireGuardBackend;
+
+mod common;
+
+/// Checks that every character of a key's text can occur where the layout
+/// says, so no pattern that could match is rejected, that the text scored is
+/// the variable part of that text, that a key is found by
+/// the characters at the start of its own text, and that saving never
+/// overwrites a key and a saved key can be removed again, as the leaderboard
+/// does with keys that drop out of it.
+fn assert_keeps_contract<B: KeyBackend>(backend: B, name: &str) {
+    let layout = backend.layout();
+    let mut keypairs = backend.generate_batch(20);
+    let mut variable = Vec::new();
+    for keypair in &keypairs {
+        let text = backend.text(keypair);
+        assert_eq!(text.len(), layout.len(), "{}: {}", name, text);
+        backend.variable_text(keypair, &mut variable);
+        assert_eq!(
+            variable,
+            &text.as_bytes()[layout.variable_start()..],
+            "{}: {}",
+            name,
+            text
+        );
+        for (i, c) in text.bytes().enumerate() {
+            assert!(
+                layout.position(i).contains(c),
+                "{}: {:?} at {} of {}",
+                name,
+                c as char,
+                i,
+                text
+            );
+        }
+    }
+
+    let keypair = keypairs.remove(0);
+    let text = backend.text(&keypair);
+    let start = layout.variable_start();
+    let pattern = format!("prefix:{}", &text[start..start + 4]);
+    let patterns = PatternSet::new(vec![
+        Pattern::new_for_layout(pattern.clone(), layout).unwrap(),
+    ]);
+    assert!(
+        backend.find_match(&keypair, &patterns).is_some(),
+        "{}: {} in {}",
+        name,
+        pattern,
+        text
+    );
+
+    let dir = common::out_dir(&format!("contract-{}", name));
+    let mut writer = common::writer(backend, dir.clone(), None);
+    let first = writer.save(&keypair, "key").unwrap();
+    let second = writer.save(&keypair, "key").unwrap();
+    assert_ne!(first, second, "{}: an existing key was overwritten", name);
+    writer.remove(&first).unwrap();
+    assert!(!first.exists(), "{}", name);
+    assert!(second.exists(), "{}", name);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ed25519_keeps_the_contract() {
+    assert_keeps_contract(
+        Ed25519Backend {
+            strategy: Strategy::Seed,
+        },
+        "ed25519",
+    );
+}
+
+#[test]
+fn ecdsa_keeps_the_contract() {
+    for curve in [Curve::NistP256, Curve::NistP384, Curve::NistP521] {
+        assert_keeps_contract(EcdsaBackend { curve }, curve.name());
+    }
+}
+
+#[test]
+fn rsa_keeps_the_contract() {
+    assert_keeps_contract(RsaBackend::generate(2048).unwrap(), "rsa");
+}
+
+#[test]
+fn onion_keeps_the_contract() {
+    for (strategy, name) in [
+        (Strategy::Seed, "onion-seed"),
+        (Strategy::PointWalk, "onion-point-walk"),
+    ] {
+        assert_keeps_contract(OnionBackend { strategy }, name);
+    }
+}
+
+#[test]
+fn wireguard_keeps_the_contract() {
+    for (strategy, name) in [
+        (Strategy::Seed, "wireguard-seed"),
+        (Strategy::PointWalk, "wireguard-point-walk"),
+    ] {
+        assert_keeps_contract(WireGuardBackend { strategy }, name);
+    }
+}
@@ -0,0 +1,30 @@
+//! Helpers for writing keys in the tests of each backend.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+
+use vanity_ssh_rs::core::backend::KeyBackend;
+use vanity_ssh_rs::core::file_io::{KeyEncryption, KeyWriter};
+
+/// A fresh directory under the system temp dir for one test's keys.
+pub fn out_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vanity-ssh-rs-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+pub fn writer<B: KeyBackend>(
+    backend: B,
+    dir: PathBuf,
+    encryption: Option<KeyEncryption>,
+) -> KeyWriter<B> {
+    KeyWriter::new(
+        backend,
+        dir,
+        "{pattern}".to_string(),
+        "test@vanity".to_string(),
+        encryption,
+    )
+    .unwrap()
+}
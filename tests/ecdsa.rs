@@ -0,0 +1,74 @@
+use ssh_key::{Algorithm, EcdsaCurve, PrivateKey, PublicKey};
+use std::fs;
+
+use vanity_ssh_rs::core::backend::KeyBackend;
+use vanity_ssh_rs::core::ecdsa::{Curve, EcdsaBackend};
+use vanity_ssh_rs::core::file_io::public_key_path;
+use vanity_ssh_rs::core::fingerprint::openssh_fingerprint;
+use vanity_ssh_rs::core::keypair::BATCH_SIZE;
+
+mod common;
+
+const CURVES: [(Curve, EcdsaCurve); 3] = [
+    (Curve::NistP256, EcdsaCurve::NistP256),
+    (Curve::NistP384, EcdsaCurve::NistP384),
+    (Curve::NistP521, EcdsaCurve::NistP521),
+];
+
+#[test]
+fn saved_keys_load_with_ssh_key() {
+    for (curve, ssh_curve) in CURVES {
+        let backend = EcdsaBackend { curve };
+        let keypair = backend.generate_batch(1).remove(0);
+        let text = backend.text(&keypair);
+        let fingerprint = backend.fingerprint(&keypair);
+        let dir = common::out_dir(&format!("ecdsa-saved-{}", curve.name()));
+        let path = common::writer(backend, dir.clone(), None)
+            .save(&keypair, curve.name())
+            .unwrap();
+
+        let private_key = PrivateKey::read_openssh_file(&path).unwrap();
+        assert_eq!(
+            private_key.algorithm(),
+            Algorithm::Ecdsa { curve: ssh_curve }
+        );
+        assert_eq!(
+            private_key
+                .public_key()
+                .key_data()
+                .ecdsa()
+                .unwrap()
+                .as_sec1_bytes(),
+            keypair.public_key.as_slice()
+        );
+
+        let public_key = PublicKey::read_openssh_file(&public_key_path(&path)).unwrap();
+        let openssh = public_key.to_openssh().unwrap();
+        assert_eq!(
+            openssh.split(' ').nth(1).unwrap().trim_end_matches('='),
+            text
+        );
+        assert_eq!(openssh_fingerprint(&public_key), fingerprint);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+/// A batch walks `d + 1, d + 2, ...` from one random scalar, so consecutive
+/// keys differ by one in their scalar.
+#[test]
+fn batch_steps_the_scalar_by_one() {
+    for (curve, _) in CURVES {
+        let keypairs = EcdsaBackend { curve }.generate_batch(BATCH_SIZE + 2);
+        for pair in keypairs.windows(2) {
+            let mut next = pair[0].scalar().to_vec();
+            for byte in next.iter_mut().rev() {
+                *byte = byte.wrapping_add(1);
+                if *byte != 0 {
+                    break;
+                }
+            }
+            assert_eq!(next, pair[1].scalar(), "{}", curve.name());
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use ed25519_dalek::SigningKey;
+use ssh_key::{Algorithm, PrivateKey, PublicKey};
+use std::fs;
+use zeroize::Zeroizing;
+
+use vanity_ssh_rs::core::backend::KeyBackend;
+use vanity_ssh_rs::core::file_io::{KeyEncryption, public_key_path};
+use vanity_ssh_rs::core::fingerprint::openssh_fingerprint;
+use vanity_ssh_rs::core::keypair::{Ed25519Backend, Secret, Strategy};
+
+mod common;
+
+const BACKEND: Ed25519Backend = Ed25519Backend {
+    strategy: Strategy::Seed,
+};
+
+#[test]
+fn saved_key_loads_with_ssh_key() {
+    let keypair = BACKEND.generate_batch(1).remove(0);
+    let text = BACKEND.text(&keypair);
+    let fingerprint = BACKEND.fingerprint(&keypair);
+    let dir = common::out_dir("ed25519-saved");
+    let path = common::writer(BACKEND, dir.clone(), None)
+        .save(&keypair, "ed25519")
+        .unwrap();
+
+    let private_key = PrivateKey::read_openssh_file(&path).unwrap();
+    assert_eq!(private_key.algorithm(), Algorithm::Ed25519);
+    assert_eq!(
+        private_key.public_key().key_data().ed25519().unwrap().0,
+        keypair.public_key.to_bytes()
+    );
+
+    let public_key = PublicKey::read_openssh_file(&public_key_path(&path)).unwrap();
+    let openssh = public_key.to_openssh().unwrap();
+    assert_eq!(
+        openssh.split(' ').nth(1).unwrap().trim_end_matches('='),
+        text
+    );
+    assert_eq!(openssh_fingerprint(&public_key), fingerprint);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn encrypted_key_loads_with_its_passphrase() {
+    let keypair = BACKEND.generate_batch(1).remove(0);
+    let Secret::Seed(seed) = &keypair.secret_key else {
+        panic!("seed strategy produced an expanded secret");
+    };
+    let dir = common::out_dir("ed25519-encrypted");
+    let encryption = KeyEncryption {
+        passphrase: Zeroizing::new("correct horse".to_string()),
+        kdf_rounds: 1,
+    };
+    let path = common::writer(BACKEND, dir.clone(), Some(encryption))
+        .save(&keypair, "ed25519")
+        .unwrap();
+
+    let private_key = PrivateKey::read_openssh_file(&path).unwrap();
+    assert!(private_key.is_encrypted());
+    assert!(private_key.decrypt("wrong horse").is_err());
+    assert!(private_key.decrypt("").is_err());
+    let decrypted = private_key.decrypt("correct horse").unwrap();
+    let ed25519 = decrypted.key_data().ed25519().unwrap();
+    assert_eq!(ed25519.public.0, keypair.public_key.to_bytes());
+    assert_eq!(ed25519.private.to_bytes(), *seed.expose());
+
+    // The public key stays readable without the passphrase.
+    let public_key = PublicKey::read_openssh_file(&public_key_path(&path)).unwrap();
+    assert_eq!(public_key.key_data(), decrypted.public_key().key_data());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// OpenSSH keys are stored as seeds, which the point walk does not have.
+#[test]
+fn point_walk_keys_are_not_written() {
+    let backend = Ed25519Backend {
+        strategy: Strategy::PointWalk,
+    };
+    let keypair = backend.generate_batch(1).remove(0);
+    let dir = common::out_dir("ed25519-point-walk");
+    let result = common::writer(backend, dir.clone(), None).save(&keypair, "walk");
+    assert!(result.is_err());
+    let _ = fs::remove_dir_all(dir);
+}
+
+/// OpenSSH stores the seed, so every key of a batch must be the one its seed
+/// gives.
+#[test]
+fn batch_keys_derive_from_their_seeds() {
+    for keypair in BACKEND.generate_batch(20) {
+        let Secret::Seed(seed) = &keypair.secret_key else {
+            panic!("seed strategy produced an expanded secret");
+        };
+        let signing_key = SigningKey::from_bytes(seed.expose());
+        assert_eq!(
+            signing_key.verifying_key().to_bytes(),
+            keypair.public_key.to_bytes()
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use curve25519_dalek::edwards::EdwardsPoint;
+use std::fs;
+
+use vanity_ssh_rs::core::backend::KeyBackend;
+use vanity_ssh_rs::core::keypair::Strategy;
+use vanity_ssh_rs::core::onion::{OnionBackend, onion_address};
+
+mod common;
+
+const BACKENDS: [OnionBackend; 2] = [
+    OnionBackend {
+        strategy: Strategy::Seed,
+    },
+    OnionBackend {
+        strategy: Strategy::PointWalk,
+    },
+];
+
+#[test]
+fn saved_service_has_tor_files() {
+    for (i, backend) in BACKENDS.into_iter().enumerate() {
+        let keypair = backend.generate_batch(1).remove(0);
+        let address = backend.text(&keypair);
+        assert_eq!(backend.fingerprint(&keypair), address);
+        let dir = common::out_dir(&format!("onion-saved-{}", i));
+        let path = common::writer(backend, dir.clone(), None)
+            .save(&keypair, "service")
+            .unwrap();
+
+        let hostname = fs::read_to_string(path.join("hostname")).unwrap();
+        assert_eq!(hostname, format!("{}.onion\n", address));
+        let public_key = fs::read(path.join("hs_ed25519_public_key")).unwrap();
+        assert_eq!(public_key[..32], *b"== ed25519v1-public: type0 ==\0\0\0");
+        assert_eq!(public_key[32..], keypair.public_key.to_bytes());
+        let secret_key = fs::read(path.join("hs_ed25519_secret_key")).unwrap();
+        assert_eq!(secret_key[..32], *b"== ed25519v1-secret: type0 ==\0\0\0");
+        assert_eq!(secret_key.len(), 96);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+/// Tor derives the service's address from the scalar in
+/// `hs_ed25519_secret_key`, which for the point walk is the only form the
+/// secret has.
+#[test]
+fn saved_secret_key_derives_the_address() {
+    for (i, backend) in BACKENDS.into_iter().enumerate() {
+        let keypair = backend.generate_batch(1).remove(0);
+        let dir = common::out_dir(&format!("onion-derived-{}", i));
+        let path = common::writer(backend, dir.clone(), None)
+            .save(&keypair, "service")
+            .unwrap();
+
+        let secret_key = fs::read(path.join("hs_ed25519_secret_key")).unwrap();
+        let scalar: [u8; 32] = secret_key[32..64].try_into().unwrap();
+        let public_key = EdwardsPoint::mul_base_clamped(scalar).compress();
+        let hostname = fs::read_to_string(path.join("hostname")).unwrap();
+        assert_eq!(
+            hostname,
+            format!("{}.onion\n", onion_address(public_key.as_bytes()))
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+/// Keys of one point walk have scalars a known step apart, so each needs
+/// its own nonce prefix or two signatures would give the scalar away.
+#[test]
+fn point_walk_keys_have_their_own_nonce_prefix() {
+    let keypairs = BACKENDS[1].generate_batch(2);
+    let first = keypairs[0].secret_key.expanded();
+    let second = keypairs[1].secret_key.expanded();
+    assert_ne!(first.expose()[..32], second.expose()[..32]);
+    assert_ne!(first.expose()[32..], second.expose()[32..]);
+}
@@ -0,0 +1,118 @@
+use rsa::traits::PublicKeyParts;
+use ssh_key::{Algorithm, PrivateKey, PublicKey};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use zeroize::Zeroizing;
+
+use vanity_ssh_rs::core::backend::KeyBackend;
+use vanity_ssh_rs::core::file_io::{KeyEncryption, public_key_path};
+use vanity_ssh_rs::core::fingerprint::openssh_fingerprint;
+use vanity_ssh_rs::core::rsa::{RsaBackend, RsaKeyPair};
+
+mod common;
+
+/// The smallest modulus `ssh-key` accepts, which is also the quickest to
+/// generate. Every test shares it, as the keys of a run do.
+fn backend() -> RsaBackend {
+    static BACKEND: OnceLock<RsaBackend> = OnceLock::new();
+    *BACKEND.get_or_init(|| RsaBackend::generate(2048).unwrap())
+}
+
+fn first_keypair() -> RsaKeyPair {
+    backend().generate_batch(1).remove(0)
+}
+
+fn save(keypair: RsaKeyPair, dir: PathBuf, encryption: Option<KeyEncryption>) -> PathBuf {
+    common::writer(backend(), dir, encryption)
+        .save(&keypair, "rsa")
+        .unwrap()
+}
+
+#[test]
+fn batch_varies_only_the_exponent() {
+    let keypairs = backend().generate_batch(20);
+    let first = keypairs[0].blob();
+    for keypair in &keypairs {
+        assert_eq!(keypair.exponent % 2, 1);
+        assert!((0x4000_0000..0x8000_0000).contains(&keypair.exponent));
+        // Everything after the exponent is the modulus.
+        assert_eq!(keypair.blob()[19..], first[19..]);
+    }
+}
+
+/// Only the 6 characters covering the exponent vary, so patterns anywhere
+/// else are rejected instead of searched for forever.
+#[test]
+fn layout_fixes_everything_but_the_exponent() {
+    let layout = backend().layout();
+    assert_eq!(layout.fixed_prefix(), "AAAAB3NzaC1yc2EAAAAE");
+    for i in 0..layout.len() {
+        assert_eq!(layout.position(i).len() > 1, (20..26).contains(&i), "{}", i);
+    }
+}
+
+#[test]
+fn saved_key_loads_with_ssh_key() {
+    let keypair = first_keypair();
+    let exponent = keypair.exponent;
+    let blob = keypair.blob();
+    let text = keypair.text();
+    let fingerprint = backend().fingerprint(&keypair);
+    let dir = common::out_dir("rsa-saved");
+    let path = save(keypair, dir.clone(), None);
+
+    let private_key = PrivateKey::read_openssh_file(&path).unwrap();
+    assert_eq!(private_key.algorithm(), Algorithm::Rsa { hash: None });
+    assert_eq!(private_key.public_key().to_bytes().unwrap(), blob);
+    assert_eq!(private_key.comment(), "test@vanity");
+
+    // ssh-key's own conversion to `rsa::RsaPrivateKey` passes `p` twice, so
+    // the key is rebuilt from its fields, which also validates it.
+    let key_data = private_key.key_data().rsa().unwrap();
+    let big =
+        |mpint: &ssh_key::Mpint| rsa::BigUint::from_bytes_be(mpint.as_positive_bytes().unwrap());
+    let rsa_key = rsa::RsaPrivateKey::from_components(
+        big(&key_data.public.n),
+        big(&key_data.public.e),
+        big(&key_data.private.d),
+        vec![big(&key_data.private.p), big(&key_data.private.q)],
+    )
+    .unwrap();
+    assert_eq!(rsa_key.e(), &rsa::BigUint::from(exponent));
+
+    let public_key = PublicKey::read_openssh_file(&public_key_path(&path)).unwrap();
+    // Patterns match the text without its `=` padding.
+    let openssh = public_key.to_openssh().unwrap();
+    let base64 = openssh.split(' ').nth(1).unwrap();
+    assert_eq!(base64.trim_end_matches('='), text);
+    assert_eq!(openssh_fingerprint(&public_key), fingerprint);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn encrypted_key_loads_with_its_passphrase() {
+    let keypair = first_keypair();
+    let blob = keypair.blob();
+    let dir = common::out_dir("rsa-encrypted");
+    let encryption = KeyEncryption {
+        passphrase: Zeroizing::new("correct horse".to_string()),
+        kdf_rounds: 1,
+    };
+    let path = save(keypair, dir.clone(), Some(encryption));
+
+    let private_key = PrivateKey::read_openssh_file(&path).unwrap();
+    assert!(private_key.is_encrypted());
+    assert!(private_key.decrypt("wrong horse").is_err());
+    let decrypted = private_key.decrypt("correct horse").unwrap();
+    assert_eq!(decrypted.public_key().to_bytes().unwrap(), blob);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// A search can only try the odd exponents between 2^30 and 2^31.
+#[test]
+fn key_space_counts_the_exponents() {
+    assert_eq!(backend().key_space(), Some(2f64.powi(29)));
+}
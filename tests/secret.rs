@@ -0,0 +1,88 @@
+use vanity_ssh_rs::core::backend::BatchKeyPair;
+use vanity_ssh_rs::core::keypair::{
+    Ed25519KeyPair, Secret, generate_keypair_batch, generate_keypair_batch_point_walk,
+};
+use vanity_ssh_rs::core::secret::SecretBytes;
+
+fn secret_bytes(keypair: &Ed25519KeyPair) -> &[u8] {
+    match &keypair.secret_key {
+        Secret::Seed(seed) => seed.expose(),
+        Secret::Scalar(scalar) => scalar.expose(),
+    }
+}
+
+/// Every way the bytes could show up in `Debug` output: as the array `Debug`
+/// prints, in hex, and as a run of decimal values.
+fn assert_debug_hides(debug: &str, bytes: &[u8]) {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let decimal = bytes[..8]
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    assert!(!debug.contains(&format!("{:?}", bytes)), "{}", debug);
+    assert!(!debug.to_lowercase().contains(&hex[..16]), "{}", debug);
+    assert!(!debug.contains(&decimal), "{}", debug);
+}
+
+#[test]
+fn debug_hides_secret_bytes() {
+    let mut secret = SecretBytes::<32>::zeroed();
+    secret.expose_mut().copy_from_slice(&[0xab; 32]);
+
+    let debug = format!("{:?}", secret);
+
+    assert_debug_hides(&debug, secret.expose());
+    assert!(debug.contains("redacted"));
+}
+
+#[test]
+fn debug_hides_seed_key_pairs() {
+    for keypair in generate_keypair_batch(10) {
+        let debug = format!("{:?}", keypair);
+        assert_debug_hides(&debug, secret_bytes(&keypair));
+        assert_debug_hides(&format!("{:#?}", keypair), secret_bytes(&keypair));
+    }
+}
+
+#[test]
+fn debug_hides_point_walk_key_pairs() {
+    for keypair in generate_keypair_batch_point_walk(10) {
+        let debug = format!("{:?}", keypair);
+        assert_debug_hides(&debug, secret_bytes(&keypair));
+        assert_debug_hides(&format!("{:#?}", keypair), secret_bytes(&keypair));
+    }
+}
+
+#[test]
+fn take_moves_the_secret_and_wipes_the_original() {
+    let mut keypairs = generate_keypair_batch(2);
+    let original = secret_bytes(&keypairs[1]).to_vec();
+
+    let taken = keypairs[1].take();
+
+    assert_eq!(secret_bytes(&taken), original.as_slice());
+    assert_eq!(taken.public_key, keypairs[1].public_key);
+    assert!(secret_bytes(&keypairs[1]).iter().all(|&b| b == 0));
+}
+
+#[test]
+fn locked_secrets_keep_their_bytes_and_stay_hidden() {
+    let mut keypair = generate_keypair_batch(1).remove(0);
+    let original = secret_bytes(&keypair).to_vec();
+
+    // mlock may be unavailable, e.g. under a zero RLIMIT_MEMLOCK.
+    if keypair.secret_key.lock().is_err() {
+        return;
+    }
+
+    let Secret::Seed(seed) = &keypair.secret_key else {
+        panic!("seed key pairs hold a seed");
+    };
+    assert!(seed.is_locked());
+    assert_eq!(secret_bytes(&keypair), original.as_slice());
+    assert_debug_hides(&format!("{:?}", keypair), &original);
+
+    let taken = keypair.take();
+    assert_eq!(secret_bytes(&taken), original.as_slice());
+}
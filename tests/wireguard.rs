@@ -0,0 +1,78 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use std::fs;
+
+use vanity_ssh_rs::core::backend::KeyBackend;
+use vanity_ssh_rs::core::keypair::Strategy;
+use vanity_ssh_rs::core::wireguard::WireGuardBackend;
+
+mod common;
+
+const BACKENDS: [WireGuardBackend; 2] = [
+    WireGuardBackend {
+        strategy: Strategy::Seed,
+    },
+    WireGuardBackend {
+        strategy: Strategy::PointWalk,
+    },
+];
+
+#[test]
+fn saved_private_key_derives_the_public_key() {
+    for (i, backend) in BACKENDS.into_iter().enumerate() {
+        let keypair = backend.generate_batch(1).remove(0);
+        let text = backend.text(&keypair);
+        let dir = common::out_dir(&format!("wireguard-saved-{}", i));
+        let path = common::writer(backend, dir.clone(), None)
+            .save(&keypair, "peer")
+            .unwrap();
+
+        let private_key = fs::read_to_string(path.join("privatekey")).unwrap();
+        let public_key = fs::read_to_string(path.join("publickey")).unwrap();
+        assert_eq!(private_key.len(), 45);
+        assert_eq!(public_key, format!("{}=\n", text));
+
+        // What `wg pubkey` does with the private key.
+        let secret: [u8; 32] = STANDARD
+            .decode(private_key.trim_end())
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let derived = MontgomeryPoint::mul_base_clamped(secret);
+        assert_eq!(STANDARD.encode(derived.as_bytes()), public_key.trim_end());
+
+        let interface = fs::read_to_string(path.join("interface.conf")).unwrap();
+        assert_eq!(
+            interface,
+            format!(
+                "[Interface]\nPrivateKey = {}# PublicKey = {}",
+                private_key, public_key
+            )
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+/// The private key is never readable by anyone else, not even while it is
+/// being written.
+#[cfg(unix)]
+#[test]
+fn private_files_are_owner_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let backend = BACKENDS[0];
+    let keypair = backend.generate_batch(1).remove(0);
+    let dir = common::out_dir("wireguard-modes");
+    let path = common::writer(backend, dir.clone(), None)
+        .save(&keypair, "peer")
+        .unwrap();
+
+    let mode = |name: &str| fs::metadata(path.join(name)).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(""), 0o700);
+    assert_eq!(mode("privatekey"), 0o600);
+    assert_eq!(mode("interface.conf"), 0o600);
+
+    fs::remove_dir_all(dir).unwrap();
+}